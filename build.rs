@@ -55,6 +55,8 @@ fn main() {
         let mut file = File::create(&dest.join("glx_extra_bindings.rs")).unwrap();
         Registry::new(Api::Glx, (1, 4), Profile::Core, Fallbacks::All, [
                           "GLX_ARB_create_context",
+                          "GLX_ARB_create_context_profile",
+                          "GLX_ARB_create_context_robustness",
                       ])
             .write_bindings(gl_generator::StructGenerator, &mut file).unwrap();
         println!("cargo:rustc-link-lib=GL");
@@ -0,0 +1,81 @@
+//! Measures how long the hot paths of this crate take: creating contexts,
+//! making them current, creating draw buffers and reading pixels back.
+//!
+//! Prints one JSON object per line, so results can be collected by scripts:
+//!
+//!     cargo run --release --example context_churn -- [iterations]
+
+extern crate euclid;
+extern crate gleam;
+extern crate offscreen_gl_context;
+
+use euclid::Size2D;
+use gleam::gl;
+use offscreen_gl_context::{ColorAttachmentType, GLContext, GLContextAttributes, GLVersion};
+use offscreen_gl_context::{NativeGLContext, NativeGLContextMethods};
+use std::env;
+use std::time::{Duration, Instant};
+
+const DEFAULT_ITERATIONS: u32 = 100;
+
+fn nanos(duration: Duration) -> u64 {
+    duration.as_secs() * 1_000_000_000 + duration.subsec_nanos() as u64
+}
+
+fn report(name: &str, iterations: u32, total: Duration) {
+    let total = nanos(total);
+    println!("{{\"backend\": \"{:?}\", \"benchmark\": \"{}\", \"iterations\": {}, \
+              \"total_ns\": {}, \"per_iteration_ns\": {}}}",
+             NativeGLContext::backend(), name, iterations, total, total / iterations as u64);
+}
+
+fn new_context(size: Size2D<i32>) -> GLContext<NativeGLContext> {
+    GLContext::<NativeGLContext>::new(size,
+                                      GLContextAttributes::default(),
+                                      ColorAttachmentType::Texture,
+                                      gl::GlType::default(),
+                                      GLVersion::Major(2),
+                                      None).unwrap()
+}
+
+fn main() {
+    let iterations = env::args().nth(1)
+                                .and_then(|arg| arg.parse().ok())
+                                .unwrap_or(DEFAULT_ITERATIONS);
+    let size = Size2D::new(256, 256);
+
+    let start = Instant::now();
+    for _ in 0..iterations {
+        new_context(size);
+    }
+    report("create_context", iterations, start.elapsed());
+
+    // Alternate between two contexts, otherwise make_current is a no-op.
+    let first = new_context(size);
+    let second = new_context(size);
+    let start = Instant::now();
+    for _ in 0..iterations {
+        first.make_current().unwrap();
+        second.make_current().unwrap();
+    }
+    report("make_current", iterations * 2, start.elapsed());
+    drop(second);
+
+    let mut context = first;
+    context.make_current().unwrap();
+    let start = Instant::now();
+    for i in 0..iterations {
+        // Alternate sizes so drivers can't just hand the old storage back.
+        let side = if i % 2 == 0 { 512 } else { 256 };
+        context.resize(Size2D::new(side, side)).unwrap();
+    }
+    report("resize_draw_buffer", iterations, start.elapsed());
+
+    context.resize(size).unwrap();
+    let start = Instant::now();
+    for _ in 0..iterations {
+        context.gl().clear(gl::COLOR_BUFFER_BIT);
+        context.gl().read_pixels(0, 0, size.width, size.height, gl::RGBA, gl::UNSIGNED_BYTE);
+    }
+    report("read_pixels_256x256", iterations, start.elapsed());
+}
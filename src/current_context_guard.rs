@@ -0,0 +1,62 @@
+use GLContext;
+use NativeGLContextMethods;
+
+/// Keeps a context current until dropped, then makes whatever was current
+/// before current again, or unbinds if nothing was. This also happens when
+/// unwinding, so code calling into plugins or other GL users doesn't leave
+/// the wrong context bound behind.
+///
+/// Created with `GLContext::make_current_scoped`. Like with `unbind`, the
+/// draw buffer is resolved before it stops being current.
+pub struct CurrentContextGuard<'a, Native>
+    where Native: NativeGLContextMethods + 'a,
+{
+    context: &'a GLContext<Native>,
+    previous: Option<Native>,
+    restored: bool,
+}
+
+impl<'a, Native> CurrentContextGuard<'a, Native>
+    where Native: NativeGLContextMethods + 'a,
+{
+    pub fn new(context: &'a GLContext<Native>) -> Result<Self, &'static str> {
+        let previous = Native::current();
+        context.make_current()?;
+        Ok(CurrentContextGuard {
+            context: context,
+            previous: previous,
+            restored: false,
+        })
+    }
+
+    /// Restores the previous context now, reporting failures that dropping
+    /// the guard can only log.
+    pub fn restore(mut self) -> Result<(), &'static str> {
+        self.restore_previous()
+    }
+
+    fn restore_previous(&mut self) -> Result<(), &'static str> {
+        if self.restored {
+            return Ok(());
+        }
+        self.restored = true;
+
+        match self.previous.take() {
+            Some(previous) => {
+                self.context.resolve_draw_buffer();
+                previous.make_current()
+            }
+            None => self.context.unbind(),
+        }
+    }
+}
+
+impl<'a, Native> Drop for CurrentContextGuard<'a, Native>
+    where Native: NativeGLContextMethods + 'a,
+{
+    fn drop(&mut self) {
+        if let Err(err) = self.restore_previous() {
+            warn!("Couldn't restore the previous GL context: {}", err);
+        }
+    }
+}
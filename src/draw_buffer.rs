@@ -1,12 +1,15 @@
 use euclid::Size2D;
 use gleam::gl;
-use gleam::gl::types::{GLuint, GLenum, GLint};
+use gleam::gl::types::{GLuint, GLenum, GLint, GLsizei};
+use std::cmp;
+use std::mem;
 use std::rc::Rc;
 
 use GLContext;
 use NativeGLContextMethods;
+use OUT_OF_MEMORY;
 
-#[derive(Debug)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
 pub enum ColorAttachmentType {
     Texture,
     Renderbuffer,
@@ -31,13 +34,6 @@ pub enum ColorAttachment {
 }
 
 impl ColorAttachment {
-    pub fn color_attachment_type(&self) -> ColorAttachmentType {
-        match *self {
-            ColorAttachment::Renderbuffer(_) => ColorAttachmentType::Renderbuffer,
-            ColorAttachment::Texture(_) => ColorAttachmentType::Texture,
-        }
-    }
-
     fn destroy(self, gl: &gl::Gl) {
         match self {
             ColorAttachment::Renderbuffer(id) => gl.delete_renderbuffers(&[id]),
@@ -46,45 +42,132 @@ impl ColorAttachment {
     }
 }
 
+/// The storage of a draw buffer, set aside while a resize allocates the new
+/// one, so that it can be put back if that fails.
+struct Attachments {
+    size: Size2D<i32>,
+    color_attachment: Option<ColorAttachment>,
+    stencil_renderbuffer: GLuint,
+    depth_renderbuffer: GLuint,
+    packed_depth_stencil_renderbuffer: GLuint,
+    multisample_color_renderbuffer: GLuint,
+    immutable_storage: bool,
+    color_format: GLenum,
+    bytes_per_pixel: usize,
+    samples: GLsizei,
+}
+
+impl Attachments {
+    fn destroy(self, gl: &dyn gl::Gl) {
+        if let Some(att) = self.color_attachment {
+            att.destroy(gl);
+        }
+        gl.delete_renderbuffers(&[self.multisample_color_renderbuffer,
+                                  self.stencil_renderbuffer,
+                                  self.depth_renderbuffer,
+                                  self.packed_depth_stencil_renderbuffer]);
+    }
+}
+
 /// This structure represents an offscreen context
 /// draw buffer. It has a framebuffer, with at least
 /// color renderbuffer (alpha or not). It may also have
 /// packed or independent depth or stencil buffers,
 /// depending on context requirements.
+///
+/// Its GL objects are deleted on drop, so it must be dropped with its
+/// context current.
 pub struct DrawBuffer {
     gl_: Rc<gl::Gl>,
     size: Size2D<i32>,
     framebuffer: GLuint,
+    color_attachment_type: ColorAttachmentType,
     color_attachment: Option<ColorAttachment>,
     stencil_renderbuffer: GLuint,
     depth_renderbuffer: GLuint,
     packed_depth_stencil_renderbuffer: GLuint,
-    // samples: GLsizei,
+    immutable_storage: bool,
+    color_format: GLenum,
+    bytes_per_pixel: usize,
+    samples: GLsizei,
+    // When antialiasing, `framebuffer` renders into multisampled
+    // renderbuffers, and the color attachment lives in this one instead.
+    multisample_color_renderbuffer: GLuint,
+    resolve_framebuffer: GLuint,
+    // OpenGL (ES) 3 has pixel pack buffers and separate read and draw
+    // framebuffer bindings, which readbacks have to leave alone.
+    is_gl3: bool,
 }
 
-/// Helper function to create a render buffer
-/// TODO(emilio): We'll need to switch between `glRenderbufferStorage` and
-/// `glRenderbufferStorageMultisample` when we support antialising
+/// The number of samples we ask for when antialiasing, if available.
+const PREFERRED_SAMPLES: GLsizei = 4;
+
+// See https://github.com/servo/servo/issues/12320
+const MIN_DRAWING_BUFFER_SIZE: i32 = 16;
+
+fn clamp_size(size: Size2D<i32>) -> Size2D<i32> {
+    Size2D::new(cmp::max(MIN_DRAWING_BUFFER_SIZE, size.width),
+                cmp::max(MIN_DRAWING_BUFFER_SIZE, size.height))
+}
+
+// gleam doesn't expose glRenderbufferStorageMultisample, so we load it
+// ourselves.
+type RenderbufferStorageMultisampleFn =
+    extern "system" fn(GLenum, GLsizei, GLenum, GLsizei, GLsizei);
+
+/// Helper function to create a render buffer, multisampled if `multisample`
+/// is given.
 fn create_renderbuffer(gl_: &gl::Gl,
                        format: GLenum,
-                       size: &Size2D<i32>) -> GLuint {
+                       size: &Size2D<i32>,
+                       multisample: Option<(RenderbufferStorageMultisampleFn, GLsizei)>)
+                       -> Result<GLuint, &'static str> {
     let ret = gl_.gen_renderbuffers(1)[0];
     gl_.bind_renderbuffer(gl::RENDERBUFFER, ret);
-    gl_.renderbuffer_storage(gl::RENDERBUFFER, format, size.width, size.height);
+    match multisample {
+        Some((storage_multisample, samples)) => {
+            storage_multisample(gl::RENDERBUFFER, samples, format, size.width, size.height);
+        }
+        None => {
+            gl_.renderbuffer_storage(gl::RENDERBUFFER, format, size.width, size.height);
+        }
+    }
+
+    // Some drivers (Mesa for one) don't flag GL_OUT_OF_MEMORY when they
+    // can't allocate the storage, and leave an empty renderbuffer instead.
+    // The size was checked against the limits already, so that's the only
+    // reason for it to be empty.
+    let width = gl_.get_renderbuffer_parameter_iv(gl::RENDERBUFFER, gl::RENDERBUFFER_WIDTH);
     gl_.bind_renderbuffer(gl::RENDERBUFFER, 0);
 
-    ret
+    if width == 0 {
+        gl_.delete_renderbuffers(&[ret]);
+        return Err(OUT_OF_MEMORY);
+    }
+
+    Ok(ret)
+}
+
+/// Helper function to estimate how many bytes a pixel of a given internal
+/// format takes. Drivers usually pad 24-bit formats to 32 bits, so we
+/// account for that.
+fn bytes_per_pixel(format: GLenum) -> usize {
+    match format {
+        gl::RGBA8 | gl::RGB8 | gl::DEPTH_COMPONENT24 | gl::DEPTH24_STENCIL8 => 4,
+        gl::RGBA4 | gl::RGB565 | gl::DEPTH_COMPONENT16 => 2,
+        gl::STENCIL_INDEX8 => 1,
+        _ => 4,
+    }
 }
 
 impl DrawBuffer {
+    /// Creates a draw buffer for `context`. If the driver runs out of memory
+    /// this returns `Err(OUT_OF_MEMORY)` without leaking any GL object.
     pub fn new<T: NativeGLContextMethods>(context: &GLContext<T>,
-                                          mut size: Size2D<i32>,
+                                          size: Size2D<i32>,
                                           color_attachment_type: ColorAttachmentType)
                                           -> Result<Self, &'static str>
     {
-        const MIN_DRAWING_BUFFER_SIZE: i32 = 16;
-        use std::cmp;
-
         let attrs = context.borrow_attributes();
         let capabilities = context.borrow_capabilities();
 
@@ -93,37 +176,43 @@ impl DrawBuffer {
 
         // WebGL spec: antialias attribute is a requests, not a requirement.
         // If not supported it shall not cause a failure to create a WebGLRenderingContext.
-        if attrs.antialias && capabilities.max_samples == 0 {
+        let samples = if !attrs.antialias {
+            0
+        } else if capabilities.max_samples == 0 {
             error!("The given GLContext doesn't support requested antialising");
-        }
+            0
+        } else {
+            cmp::min(PREFERRED_SAMPLES, capabilities.max_samples)
+        };
 
         if attrs.preserve_drawing_buffer {
             return Err("preserveDrawingBuffer is not supported yet");
         }
 
-        // See https://github.com/servo/servo/issues/12320
-        size.width = cmp::max(MIN_DRAWING_BUFFER_SIZE, size.width);
-        size.height = cmp::max(MIN_DRAWING_BUFFER_SIZE, size.height);
-
         let mut draw_buffer = DrawBuffer {
             gl_: context.clone_gl(),
-            size: size,
+            size: clamp_size(size),
             framebuffer: 0,
+            color_attachment_type: color_attachment_type,
             color_attachment: None,
             stencil_renderbuffer: 0,
             depth_renderbuffer: 0,
             packed_depth_stencil_renderbuffer: 0,
-            // samples: 0,
+            immutable_storage: false,
+            color_format: 0,
+            bytes_per_pixel: 0,
+            samples: samples,
+            multisample_color_renderbuffer: 0,
+            resolve_framebuffer: 0,
+            is_gl3: false,
         };
 
         try!(context.make_current());
+        draw_buffer.is_gl3 = context.api_version()?.major_version() >= 3;
 
+        draw_buffer.discard_pending_gl_error();
         try!(draw_buffer.init(context, color_attachment_type));
-
-        debug_assert_eq!(draw_buffer.gl().check_frame_buffer_status(gl::FRAMEBUFFER),
-                         gl::FRAMEBUFFER_COMPLETE);
-        debug_assert_eq!(draw_buffer.gl().get_error(),
-                         gl::NO_ERROR);
+        draw_buffer.finish_init()?;
 
         Ok(draw_buffer)
     }
@@ -133,36 +222,366 @@ impl DrawBuffer {
         self.framebuffer
     }
 
+    /// Reallocates the attachments with a new size, keeping the framebuffer
+    /// ids, so whoever holds on to them doesn't need to know. The color
+    /// attachment gets a new id though. The context must be current.
+    ///
+    /// The old attachments are only released once the new ones work. On
+    /// failure, e.g. when running out of memory, they're put back, and the
+    /// draw buffer keeps its size and contents.
+    pub fn resize<T: NativeGLContextMethods>(&mut self,
+                                             context: &GLContext<T>,
+                                             size: Size2D<i32>)
+                                             -> Result<(), &'static str> {
+        let old_attachments = self.take_attachments();
+        self.size = clamp_size(size);
+
+        let color_attachment_type = self.color_attachment_type;
+        self.discard_pending_gl_error();
+        let result = match self.init(context, color_attachment_type) {
+            Ok(()) => self.finish_init(),
+            Err(err) => Err(err),
+        };
+
+        match result {
+            Ok(()) => {
+                old_attachments.destroy(self.gl());
+                Ok(())
+            }
+            Err(err) => {
+                self.release_attachments();
+                self.restore_attachments(old_attachments);
+                self.attach_to_framebuffer()?;
+                Err(err)
+            }
+        }
+    }
+
+    /// The framebuffer the color attachment can be read from. It's not the
+    /// one we draw to when antialiasing, and reads from it only see what was
+    /// drawn up to the last `resolve()`.
+    #[inline(always)]
+    pub fn get_read_framebuffer(&self) -> GLuint {
+        if self.needs_resolve() { self.resolve_framebuffer } else { self.framebuffer }
+    }
+
     #[inline(always)]
     pub fn size(&self) -> Size2D<i32> {
         self.size
     }
 
     #[inline(always)]
-    // NOTE: We unwrap here because after creation the draw buffer
-    // always have a color attachment
     pub fn color_attachment_type(&self) -> ColorAttachmentType {
-        self.color_attachment.as_ref().unwrap().color_attachment_type()
+        self.color_attachment_type
     }
 
+    // NOTE: These are only `None` for the other attachment type.
     pub fn get_bound_color_renderbuffer_id(&self) -> Option<GLuint> {
-        match self.color_attachment.as_ref().unwrap() {
-            &ColorAttachment::Renderbuffer(id) => Some(id),
+        match self.color_attachment {
+            Some(ColorAttachment::Renderbuffer(id)) => Some(id),
             _ => None,
         }
     }
 
     pub fn get_bound_texture_id(&self) -> Option<GLuint> {
-        match self.color_attachment.as_ref().unwrap() {
-            &ColorAttachment::Renderbuffer(_) => None,
-            &ColorAttachment::Texture(id) => Some(id),
+        match self.color_attachment {
+            Some(ColorAttachment::Texture(id)) => Some(id),
+            _ => None,
+        }
+    }
+
+    /// The internal format of the color attachment. It's sized (e.g.
+    /// `gl::RGBA8`) for renderbuffers and immutable textures, and the
+    /// unsized format the texture was allocated with (e.g. `gl::RGBA`)
+    /// otherwise.
+    #[inline(always)]
+    pub fn color_format(&self) -> GLenum {
+        self.color_format
+    }
+
+    /// An estimate of the video memory used by this draw buffer, in bytes,
+    /// counting the color attachment and any depth or stencil buffers.
+    pub fn memory_usage(&self) -> usize {
+        self.bytes_per_pixel * self.size.width as usize * self.size.height as usize
+    }
+
+    /// Whether the color texture was allocated with immutable storage
+    /// (`glTexStorage2D`), which some consumers require. Always false for
+    /// renderbuffer color attachments.
+    pub fn has_immutable_storage(&self) -> bool {
+        self.immutable_storage
+    }
+
+    /// The number of samples per pixel we render with, or zero if we don't
+    /// antialias.
+    #[inline(always)]
+    pub fn samples(&self) -> GLsizei {
+        self.samples
+    }
+
+    /// Whether `get_framebuffer()` renders into multisampled buffers, in which
+    /// case the color attachment only gets the rendered contents after a call
+    /// to `resolve()`.
+    #[inline(always)]
+    pub fn needs_resolve(&self) -> bool {
+        self.samples > 0
+    }
+
+    /// Resolves the multisampled buffers into the color attachment. Does
+    /// nothing if we're not antialiasing. The context must be current.
+    pub fn resolve(&self) {
+        if !self.needs_resolve() {
+            return;
+        }
+
+        let mut read_framebuffer = [0];
+        let mut draw_framebuffer = [0];
+        unsafe {
+            self.gl().get_integer_v(gl::READ_FRAMEBUFFER_BINDING, &mut read_framebuffer);
+            self.gl().get_integer_v(gl::DRAW_FRAMEBUFFER_BINDING, &mut draw_framebuffer);
+        }
+
+        // The scissor test applies to blits too.
+        let scissor_enabled = self.gl().is_enabled(gl::SCISSOR_TEST) != 0;
+        if scissor_enabled {
+            self.gl().disable(gl::SCISSOR_TEST);
+        }
+
+        self.gl().bind_framebuffer(gl::READ_FRAMEBUFFER, self.framebuffer);
+        self.gl().bind_framebuffer(gl::DRAW_FRAMEBUFFER, self.resolve_framebuffer);
+        self.gl().blit_framebuffer(0, 0, self.size.width, self.size.height,
+                                   0, 0, self.size.width, self.size.height,
+                                   gl::COLOR_BUFFER_BIT, gl::NEAREST);
+
+        if scissor_enabled {
+            self.gl().enable(gl::SCISSOR_TEST);
+        }
+
+        self.gl().bind_framebuffer(gl::READ_FRAMEBUFFER, read_framebuffer[0] as GLuint);
+        self.gl().bind_framebuffer(gl::DRAW_FRAMEBUFFER, draw_framebuffer[0] as GLuint);
+    }
+
+    /// Reads back the color attachment into `pixels` as tightly packed RGBA8
+    /// rows, with the bottom row first, like `glReadPixels`. Multisampled
+    /// draw buffers are resolved first.
+    ///
+    /// `pixels` must be exactly `width * height * 4` bytes long, and the
+    /// context must be current.
+    pub fn read_pixels_into(&self, pixels: &mut [u8]) -> Result<(), &'static str> {
+        if pixels.len() != self.size.width as usize * self.size.height as usize * 4 {
+            return Err("Pixel buffer doesn't match the draw buffer size");
+        }
+
+        self.resolve();
+
+        // A bound pixel pack buffer would get the pixels instead of
+        // `pixels`, and gleam leaves the pack alignment at 1.
+        let mut read_framebuffer = [0];
+        let mut draw_framebuffer = [0];
+        let mut pack_buffer = [0];
+        let mut pack_alignment = [0];
+        unsafe {
+            if self.is_gl3 {
+                self.gl().get_integer_v(gl::READ_FRAMEBUFFER_BINDING, &mut read_framebuffer);
+                self.gl().get_integer_v(gl::DRAW_FRAMEBUFFER_BINDING, &mut draw_framebuffer);
+                self.gl().get_integer_v(gl::PIXEL_PACK_BUFFER_BINDING, &mut pack_buffer);
+            } else {
+                self.gl().get_integer_v(gl::FRAMEBUFFER_BINDING, &mut read_framebuffer);
+            }
+            self.gl().get_integer_v(gl::PACK_ALIGNMENT, &mut pack_alignment);
         }
+
+        if self.is_gl3 {
+            self.gl().bind_buffer(gl::PIXEL_PACK_BUFFER, 0);
+        }
+        self.gl().bind_framebuffer(gl::FRAMEBUFFER, self.get_read_framebuffer());
+        self.gl().read_pixels_into_buffer(0, 0, self.size.width, self.size.height,
+                                          gl::RGBA, gl::UNSIGNED_BYTE, pixels);
+        self.gl().pixel_store_i(gl::PACK_ALIGNMENT, pack_alignment[0]);
+
+        if self.is_gl3 {
+            self.gl().bind_buffer(gl::PIXEL_PACK_BUFFER, pack_buffer[0] as GLuint);
+            self.gl().bind_framebuffer(gl::READ_FRAMEBUFFER, read_framebuffer[0] as GLuint);
+            self.gl().bind_framebuffer(gl::DRAW_FRAMEBUFFER, draw_framebuffer[0] as GLuint);
+        } else {
+            self.gl().bind_framebuffer(gl::FRAMEBUFFER, read_framebuffer[0] as GLuint);
+        }
+
+        self.check_gl_error("GL error while reading back the draw buffer")
     }
 
     fn gl(&self) -> &gl::Gl {
         &*self.gl_
     }
 
+    fn release_attachments(&mut self) {
+        if let Some(att) = self.color_attachment.take() {
+            att.destroy(self.gl());
+        }
+
+        // NOTE: Color renderbuffer is destroyed on drop of
+        //   ColorAttachment
+        self.gl().delete_renderbuffers(&[self.multisample_color_renderbuffer,
+                                         self.stencil_renderbuffer,
+                                         self.depth_renderbuffer,
+                                         self.packed_depth_stencil_renderbuffer]);
+        self.multisample_color_renderbuffer = 0;
+        self.stencil_renderbuffer = 0;
+        self.depth_renderbuffer = 0;
+        self.packed_depth_stencil_renderbuffer = 0;
+        self.immutable_storage = false;
+    }
+
+    // Moves the storage out, leaving the draw buffer without any.
+    fn take_attachments(&mut self) -> Attachments {
+        let attachments = Attachments {
+            size: self.size,
+            color_attachment: self.color_attachment.take(),
+            stencil_renderbuffer: self.stencil_renderbuffer,
+            depth_renderbuffer: self.depth_renderbuffer,
+            packed_depth_stencil_renderbuffer: self.packed_depth_stencil_renderbuffer,
+            multisample_color_renderbuffer: self.multisample_color_renderbuffer,
+            immutable_storage: self.immutable_storage,
+            color_format: self.color_format,
+            bytes_per_pixel: self.bytes_per_pixel,
+            samples: self.samples,
+        };
+
+        self.stencil_renderbuffer = 0;
+        self.depth_renderbuffer = 0;
+        self.packed_depth_stencil_renderbuffer = 0;
+        self.multisample_color_renderbuffer = 0;
+        self.immutable_storage = false;
+
+        attachments
+    }
+
+    // Puts back storage moved out by `take_attachments`. It still has to be
+    // attached to the framebuffers.
+    fn restore_attachments(&mut self, attachments: Attachments) {
+        assert!(self.color_attachment.is_none(),
+                "Would leak color attachment!");
+
+        self.size = attachments.size;
+        self.color_attachment = attachments.color_attachment;
+        self.stencil_renderbuffer = attachments.stencil_renderbuffer;
+        self.depth_renderbuffer = attachments.depth_renderbuffer;
+        self.packed_depth_stencil_renderbuffer = attachments.packed_depth_stencil_renderbuffer;
+        self.multisample_color_renderbuffer = attachments.multisample_color_renderbuffer;
+        self.immutable_storage = attachments.immutable_storage;
+        self.color_format = attachments.color_format;
+        self.bytes_per_pixel = attachments.bytes_per_pixel;
+        self.samples = attachments.samples;
+    }
+
+    // Freshly allocated storage may still hold whatever was in video memory
+    // before, so unless the `clear_on_create` feature is disabled we start
+    // from transparent black. Expects our framebuffer to be bound, and
+    // leaves the state it touches as it found it.
+    fn clear(&self) {
+        let scissor_enabled = self.gl().is_enabled(gl::SCISSOR_TEST) != 0;
+        if scissor_enabled {
+            self.gl().disable(gl::SCISSOR_TEST);
+        }
+
+        let mut clear_color = [0.0; 4];
+        let mut clear_depth = [0.0];
+        let mut clear_stencil = [0];
+        let mut color_mask = [0; 4];
+        let mut depth_mask = [0];
+        let mut stencil_mask = [0];
+        unsafe {
+            self.gl().get_float_v(gl::COLOR_CLEAR_VALUE, &mut clear_color);
+            self.gl().get_float_v(gl::DEPTH_CLEAR_VALUE, &mut clear_depth);
+            self.gl().get_integer_v(gl::STENCIL_CLEAR_VALUE, &mut clear_stencil);
+            self.gl().get_boolean_v(gl::COLOR_WRITEMASK, &mut color_mask);
+            self.gl().get_boolean_v(gl::DEPTH_WRITEMASK, &mut depth_mask);
+            self.gl().get_integer_v(gl::STENCIL_WRITEMASK, &mut stencil_mask);
+        }
+
+        // Masked channels would keep their garbage otherwise.
+        self.gl().color_mask(true, true, true, true);
+        self.gl().depth_mask(true);
+        self.gl().stencil_mask_separate(gl::FRONT, !0);
+
+        // Only touch the depth clear value if we must: glClearDepth isn't
+        // there when GLES is loaded with the desktop bindings.
+        let reset_clear_depth = clear_depth[0] != 1.0;
+        if reset_clear_depth {
+            self.gl().clear_depth(1.0);
+        }
+        self.gl().clear_color(0.0, 0.0, 0.0, 0.0);
+        self.gl().clear_stencil(0);
+        self.gl().clear(gl::COLOR_BUFFER_BIT | gl::DEPTH_BUFFER_BIT | gl::STENCIL_BUFFER_BIT);
+
+        self.gl().clear_color(clear_color[0], clear_color[1], clear_color[2], clear_color[3]);
+        if reset_clear_depth {
+            self.gl().clear_depth(clear_depth[0] as f64);
+        }
+        self.gl().clear_stencil(clear_stencil[0]);
+        self.gl().color_mask(color_mask[0] != 0, color_mask[1] != 0,
+                             color_mask[2] != 0, color_mask[3] != 0);
+        self.gl().depth_mask(depth_mask[0] != 0);
+        self.gl().stencil_mask_separate(gl::FRONT, stencil_mask[0] as GLuint);
+
+        if scissor_enabled {
+            self.gl().enable(gl::SCISSOR_TEST);
+        }
+    }
+
+
+    // Checks that the freshly allocated buffers work, and clears them if
+    // asked to.
+    fn finish_init(&self) -> Result<(), &'static str> {
+        // Drivers can be flaky here, so we'd rather report a failure than hand
+        // out a draw buffer that silently doesn't work.
+        let status = self.gl().check_frame_buffer_status(gl::FRAMEBUFFER);
+        if status != gl::FRAMEBUFFER_COMPLETE {
+            error!("Draw buffer framebuffer is incomplete: {:#x}", status);
+            return Err("Incomplete draw buffer framebuffer");
+        }
+
+        if cfg!(feature = "clear_on_create") {
+            self.clear();
+            if self.needs_resolve() {
+                self.resolve();
+            }
+        }
+
+        self.check_gl_error("GL error while creating draw buffer")
+    }
+
+    // With the `check_gl_errors` feature, errors raised by our own GL calls
+    // are turned into errors instead of being left to the embedder. Without
+    // it we never call glGetError, which would clear errors the embedder may
+    // still be expecting to see.
+    fn check_gl_error(&self, message: &'static str) -> Result<(), &'static str> {
+        if !cfg!(feature = "check_gl_errors") {
+            return Ok(());
+        }
+
+        match self.gl().get_error() {
+            gl::NO_ERROR => Ok(()),
+            gl::OUT_OF_MEMORY => Err(OUT_OF_MEMORY),
+            err => {
+                error!("{}: {:#x}", message, err);
+                Err(message)
+            }
+        }
+    }
+
+    // When checking errors, one the embedder left behind would be blamed on
+    // us, so it's logged and dropped.
+    fn discard_pending_gl_error(&self) {
+        if !cfg!(feature = "check_gl_errors") {
+            return;
+        }
+
+        let err = self.gl().get_error();
+        if err != gl::NO_ERROR {
+            warn!("Discarding GL error pending before allocating a draw buffer: {:#x}", err);
+        }
+    }
 
     fn init<T: NativeGLContextMethods>(&mut self,
                                        context: &GLContext<T>,
@@ -170,15 +589,43 @@ impl DrawBuffer {
         -> Result<(), &'static str> {
         let attrs = context.borrow_attributes();
         let formats = context.borrow_formats();
+        let limits = context.borrow_limits();
 
         assert!(self.color_attachment.is_none(),
                 "Would leak color attachment!");
+
+        let max_size = match color_attachment_type {
+            ColorAttachmentType::Renderbuffer => limits.max_renderbuffer_size,
+            ColorAttachmentType::Texture => cmp::min(limits.max_tex_size,
+                                                     limits.max_renderbuffer_size),
+        };
+        if self.size.width as u32 > max_size || self.size.height as u32 > max_size {
+            return Err("Draw buffer size exceeds the maximum supported size");
+        }
+
+        let multisample = if self.samples > 0 {
+            let storage_multisample =
+                GLContext::<T>::get_proc_address("glRenderbufferStorageMultisample");
+            if storage_multisample.is_null() {
+                error!("glRenderbufferStorageMultisample not found, disabling antialiasing");
+                self.samples = 0;
+                None
+            } else {
+                let storage_multisample: RenderbufferStorageMultisampleFn =
+                    unsafe { mem::transmute(storage_multisample) };
+                Some((storage_multisample, self.samples))
+            }
+        } else {
+            None
+        };
+
         self.color_attachment = match color_attachment_type {
             ColorAttachmentType::Renderbuffer => {
                 let color_renderbuffer =
-                    create_renderbuffer(self.gl(), formats.color_renderbuffer, &self.size);
+                    create_renderbuffer(self.gl(), formats.color_renderbuffer, &self.size, None)?;
                 debug_assert!(color_renderbuffer != 0);
 
+                self.color_format = formats.color_renderbuffer;
                 Some(ColorAttachment::Renderbuffer(color_renderbuffer))
             },
 
@@ -188,8 +635,18 @@ impl DrawBuffer {
                 debug_assert!(texture != 0);
 
                 self.gl().bind_texture(gl::TEXTURE_2D, texture);
-                self.gl().tex_image_2d(gl::TEXTURE_2D, 0,
-                                 formats.texture_internal as GLint, self.size.width, self.size.height, 0, formats.texture, formats.texture_type, None);
+                if formats.texture_storage {
+                    // Immutable storage needs a sized format, which the color
+                    // renderbuffer format always is.
+                    self.gl().tex_storage_2d(gl::TEXTURE_2D, 1, formats.color_renderbuffer,
+                                             self.size.width, self.size.height);
+                    self.immutable_storage = true;
+                    self.color_format = formats.color_renderbuffer;
+                } else {
+                    self.gl().tex_image_2d(gl::TEXTURE_2D, 0,
+                                     formats.texture_internal as GLint, self.size.width, self.size.height, 0, formats.texture, formats.texture_type, None);
+                    self.color_format = formats.texture_internal;
+                }
 
                 // Low filtering to allow rendering
                 self.gl().tex_parameter_i(gl::TEXTURE_2D, gl::TEXTURE_MAG_FILTER, gl::NEAREST as GLint);
@@ -201,40 +658,69 @@ impl DrawBuffer {
 
                 self.gl().bind_texture(gl::TEXTURE_2D, 0);
 
-                debug_assert_eq!(self.gl().get_error(), gl::NO_ERROR);
-
                 Some(ColorAttachment::Texture(texture))
             },
         };
 
+        // The color texture is allocated with the same effective format as
+        // the renderbuffer would be, even when its internal format is unsized.
+        self.bytes_per_pixel = bytes_per_pixel(formats.color_renderbuffer);
+
+        // Every buffer we render into is multisampled when antialiasing, and
+        // thus takes `samples` times the memory.
+        let sample_count = cmp::max(self.samples, 1) as usize;
+
+        if multisample.is_some() {
+            self.multisample_color_renderbuffer =
+                create_renderbuffer(self.gl(), formats.color_renderbuffer, &self.size, multisample)?;
+            debug_assert!(self.multisample_color_renderbuffer != 0);
+            self.bytes_per_pixel += sample_count * bytes_per_pixel(formats.color_renderbuffer);
+        }
+
         // After this we check if we need stencil and depth buffers
         if attrs.depth && attrs.stencil && formats.packed_depth_stencil {
-            self.packed_depth_stencil_renderbuffer = create_renderbuffer(self.gl(), gl::DEPTH24_STENCIL8, &self.size);
+            self.packed_depth_stencil_renderbuffer =
+                create_renderbuffer(self.gl(), gl::DEPTH24_STENCIL8, &self.size, multisample)?;
             debug_assert!(self.packed_depth_stencil_renderbuffer != 0);
+            self.bytes_per_pixel += sample_count * bytes_per_pixel(gl::DEPTH24_STENCIL8);
         } else {
             if attrs.depth {
-                self.depth_renderbuffer = create_renderbuffer(self.gl(), formats.depth, &self.size);
+                self.depth_renderbuffer =
+                    create_renderbuffer(self.gl(), formats.depth, &self.size, multisample)?;
                 debug_assert!(self.depth_renderbuffer != 0);
+                self.bytes_per_pixel += sample_count * bytes_per_pixel(formats.depth);
             }
 
             if attrs.stencil {
-                self.stencil_renderbuffer = create_renderbuffer(self.gl(), formats.stencil, &self.size);
+                self.stencil_renderbuffer =
+                    create_renderbuffer(self.gl(), formats.stencil, &self.size, multisample)?;
                 debug_assert!(self.stencil_renderbuffer != 0);
+                self.bytes_per_pixel += sample_count * bytes_per_pixel(formats.stencil);
             }
         }
 
-        self.framebuffer = self.gl().gen_framebuffers(1)[0];
-        debug_assert!(self.framebuffer != 0);
+        // Bail out early if the driver couldn't allocate the storage, so the
+        // embedder can free some memory and retry. The attachments we've
+        // created so far are released when the draw buffer is dropped.
+        self.check_gl_error("GL error while allocating draw buffer storage")?;
+
+        // Framebuffers survive resizes, only their attachments change.
+        if self.framebuffer == 0 {
+            self.framebuffer = self.gl().gen_framebuffers(1)[0];
+            debug_assert!(self.framebuffer != 0);
+        }
+
+        if self.samples > 0 && self.resolve_framebuffer == 0 {
+            self.resolve_framebuffer = self.gl().gen_framebuffers(1)[0];
+            debug_assert!(self.resolve_framebuffer != 0);
+        }
 
         // Finally we attach them to the framebuffer
         self.attach_to_framebuffer()
     }
 
-    fn attach_to_framebuffer(&mut self) -> Result<(), &'static str> {
-        self.gl().bind_framebuffer(gl::FRAMEBUFFER, self.framebuffer);
-        // NOTE: The assertion fails if the framebuffer is not bound
-        debug_assert_eq!(self.gl().is_framebuffer(self.framebuffer), gl::TRUE);
-
+    // Attaches the color attachment to the currently bound framebuffer.
+    fn attach_color_attachment(&self) {
         match *self.color_attachment.as_ref().unwrap() {
             ColorAttachment::Renderbuffer(color_renderbuffer) => {
                 self.gl().framebuffer_renderbuffer(gl::FRAMEBUFFER,
@@ -250,6 +736,34 @@ impl DrawBuffer {
                                                 texture_id, 0);
             },
         }
+    }
+
+    fn attach_to_framebuffer(&mut self) -> Result<(), &'static str> {
+        // When antialiasing the color attachment is only the resolve target,
+        // so it goes into its own framebuffer.
+        if self.resolve_framebuffer != 0 {
+            self.gl().bind_framebuffer(gl::FRAMEBUFFER, self.resolve_framebuffer);
+            self.attach_color_attachment();
+
+            let status = self.gl().check_frame_buffer_status(gl::FRAMEBUFFER);
+            if status != gl::FRAMEBUFFER_COMPLETE {
+                error!("Draw buffer resolve framebuffer is incomplete: {:#x}", status);
+                return Err("Incomplete draw buffer framebuffer");
+            }
+        }
+
+        self.gl().bind_framebuffer(gl::FRAMEBUFFER, self.framebuffer);
+        // NOTE: The assertion fails if the framebuffer is not bound
+        debug_assert_eq!(self.gl().is_framebuffer(self.framebuffer), gl::TRUE);
+
+        if self.multisample_color_renderbuffer != 0 {
+            self.gl().framebuffer_renderbuffer(gl::FRAMEBUFFER,
+                                               gl::COLOR_ATTACHMENT0,
+                                               gl::RENDERBUFFER,
+                                               self.multisample_color_renderbuffer);
+        } else {
+            self.attach_color_attachment();
+        }
 
         if self.packed_depth_stencil_renderbuffer != 0 {
             self.gl().framebuffer_renderbuffer(gl::FRAMEBUFFER,
@@ -285,16 +799,7 @@ impl DrawBuffer {
 // parent with Rc<GLContext> and call make_current()
 impl Drop for DrawBuffer {
     fn drop(&mut self) {
-        if let Some(att) = self.color_attachment.take() {
-            att.destroy(self.gl());
-        }
-
-        self.gl().delete_framebuffers(&[self.framebuffer]);
-
-        // NOTE: Color renderbuffer is destroyed on drop of
-        //   ColorAttachment
-        self.gl().delete_renderbuffers(&[self.stencil_renderbuffer,
-                                         self.depth_renderbuffer,
-                                         self.packed_depth_stencil_renderbuffer]);
+        self.release_attachments();
+        self.gl().delete_framebuffers(&[self.framebuffer, self.resolve_framebuffer]);
     }
 }
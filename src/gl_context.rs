@@ -1,15 +1,21 @@
 use euclid::Size2D;
 use gleam::gl;
-use gleam::gl::types::{GLuint};
+use gleam::gl::types::{GLchar, GLenum, GLsizei, GLuint};
+use std::ffi::CStr;
+use std::mem;
+use std::os::raw::c_void;
 use std::rc::Rc;
 
 use NativeGLContextMethods;
 use GLContextAttributes;
 use GLContextCapabilities;
+use GLFence;
 use GLFormats;
 use GLLimits;
 use DrawBuffer;
+use PendingReadback;
 use ColorAttachmentType;
+use CurrentContextGuard;
 
 /// This is a wrapper over a native headless GL context
 pub struct GLContext<Native> {
@@ -25,7 +31,10 @@ pub struct GLContext<Native> {
     capabilities: GLContextCapabilities,
     formats: GLFormats,
     limits: GLLimits,
-    extensions: Vec<String>
+    extensions: Vec<String>,
+    // Declared after `native_context` so that it outlives it. Double boxed
+    // so that the driver gets a thin pointer.
+    debug_callback: Option<Box<Box<dyn Fn(gl::DebugMessage)>>>,
 }
 
 impl<Native> GLContext<Native>
@@ -47,6 +56,15 @@ impl<Native> GLContext<Native>
                                                                         &api_type,
                                                                         api_version,
                                                                         dispatcher));
+        Self::from_native(native_context, api_type, api_version)
+    }
+
+    /// Wraps an already created native context, e.g. one created through a
+    /// backend-specific constructor. The context doesn't get a draw buffer.
+    pub fn from_native(native_context: Native,
+                       api_type: gl::GlType,
+                       api_version: GLVersion)
+        -> Result<Self, &'static str> {
         let gl_ = match api_type {
             gl::GlType::Gl => unsafe { gl::GlFns::load_with(|s| Self::get_proc_address(s) as *const _) },
             gl::GlType::Gles => unsafe { gl::GlesFns::load_with(|s| Self::get_proc_address(s) as *const _) },
@@ -57,16 +75,18 @@ impl<Native> GLContext<Native>
         let attributes = GLContextAttributes::any();
         let formats = GLFormats::detect(&attributes, &extensions[..], api_version);
         let limits = GLLimits::detect(&*gl_);
+        let capabilities = GLContextCapabilities::detect(&*gl_, &extensions[..]);
 
         Ok(GLContext {
             gl_: gl_,
             native_context: native_context,
             draw_buffer: None,
             attributes: attributes,
-            capabilities: GLContextCapabilities::detect(),
+            capabilities: capabilities,
             formats: formats,
             limits: limits,
-            extensions: extensions
+            extensions: extensions,
+            debug_callback: None,
         })
     }
 
@@ -104,20 +124,48 @@ impl<Native> GLContext<Native>
                                       shared_with: Option<&Native::Handle>,
                                       dispatcher: Option<Box<GLContextDispatcher>>)
         -> Result<Self, &'static str> {
+        if attributes.no_error && (attributes.debug || attributes.robust_access) {
+            return Err("no_error can't be combined with debug or robust_access");
+        }
+
         // We create a headless context with a dummy size, we're painting to the
         // draw_buffer's framebuffer anyways.
-        let mut context =
-            try!(Self::create_shared_with_dispatcher(api_type,
-                                                     api_version,
-                                                     shared_with,
-                                                     dispatcher));
+        let native_context =
+            Native::create_shared_with_attributes(shared_with,
+                                                  &api_type,
+                                                  api_version,
+                                                  &attributes,
+                                                  dispatcher)?;
+        let context = Self::from_native(native_context, api_type, api_version)?;
+
+        context.with_draw_buffer(size, attributes, color_attachment_type, api_version)
+    }
+
+    /// Like `from_native`, but also creates a draw buffer of the given size
+    /// and attributes.
+    pub fn new_from_native(native_context: Native,
+                           size: Size2D<i32>,
+                           attributes: GLContextAttributes,
+                           color_attachment_type: ColorAttachmentType,
+                           api_type: gl::GlType,
+                           api_version: GLVersion)
+        -> Result<Self, &'static str> {
+        let context = Self::from_native(native_context, api_type, api_version)?;
+        context.with_draw_buffer(size, attributes, color_attachment_type, api_version)
+    }
 
-        context.formats = GLFormats::detect(&attributes, &context.extensions[..], api_version);
-        context.attributes = attributes;
+    fn with_draw_buffer(mut self,
+                        size: Size2D<i32>,
+                        attributes: GLContextAttributes,
+                        color_attachment_type: ColorAttachmentType,
+                        api_version: GLVersion)
+        -> Result<Self, &'static str> {
+        self.formats = GLFormats::detect(&attributes, &self.extensions[..], api_version);
+        self.attributes = attributes;
 
-        try!(context.init_offscreen(size, color_attachment_type));
+        self.init_offscreen(size, color_attachment_type)?;
 
-        Ok(context)
+        Ok(self)
     }
 
     #[inline(always)]
@@ -135,8 +183,21 @@ impl<Native> GLContext<Native>
         self.native_context.make_current()
     }
 
+    /// Makes the context current even if it already is, see
+    /// `NativeGLContextMethods::make_current_force`.
+    #[inline(always)]
+    pub fn make_current_force(&self) -> Result<(), &'static str> {
+        self.native_context.make_current_force()
+    }
+
+    /// Unbinds the context. If the draw buffer is antialiased, it's resolved
+    /// first, so its color attachment is ready to be consumed elsewhere.
     #[inline(always)]
     pub fn unbind(&self) -> Result<(), &'static str> {
+        if self.is_current() {
+            self.resolve_draw_buffer();
+        }
+
         let ret = self.native_context.unbind();
 
         // OSMesa doesn't allow any API to unbind a context before [1], and just
@@ -158,11 +219,50 @@ impl<Native> GLContext<Native>
         self.native_context.is_current()
     }
 
+    /// Makes this context current, runs `f`, and then restores whatever
+    /// context was current before the call, even if it wasn't created by us,
+    /// or if `f` panics.
+    ///
+    /// This is useful when embedding into a host (a plugin, a UI toolkit...)
+    /// that owns its own GL context. If no context was current beforehand,
+    /// this context is unbound afterwards.
+    ///
+    /// NB: OSMesa can't give us back the previously current context, so in
+    /// that case it can't be restored.
+    pub fn with_current<F, R>(&self, f: F) -> Result<R, &'static str>
+        where F: FnOnce(&Self) -> R,
+    {
+        let guard = self.make_current_scoped()?;
+        let result = f(self);
+        guard.restore()?;
+
+        Ok(result)
+    }
+
+    /// Makes the context current until the returned guard is dropped, see
+    /// `CurrentContextGuard`.
+    pub fn make_current_scoped<'a>(&'a self) -> Result<CurrentContextGuard<'a, Native>, &'static str> {
+        CurrentContextGuard::new(self)
+    }
+
+    /// The OpenGL (ES) version the driver gave us. The context must be
+    /// current.
+    pub fn api_version(&self) -> Result<GLVersion, &'static str> {
+        self.native_context.api_version()
+    }
+
     #[inline(always)]
     pub fn handle(&self) -> Native::Handle {
         self.native_context.handle()
     }
 
+    /// The backend's context, for the platform-specific bits `handle()`
+    /// doesn't cover, like the surface an EGL context is bound to.
+    #[inline(always)]
+    pub fn native_context(&self) -> &Native {
+        &self.native_context
+    }
+
     pub fn gl(&self) -> &gl::Gl {
         &*self.gl_
     }
@@ -208,16 +308,152 @@ impl<Native> GLContext<Native>
         self.draw_buffer.as_ref().map(|db| db.size())
     }
 
-    // We resize just replacing the draw buffer, we don't perform size optimizations
-    // in order to keep this generic
+    /// Returns the id, size and internal format of the draw buffer's color
+    /// texture, if it has one.
+    ///
+    /// This doesn't call into GL, so the context doesn't need to be current,
+    /// and the result can be handed to another thread to set up an external
+    /// texture there.
+    pub fn draw_buffer_texture(&self) -> Option<(GLuint, Size2D<i32>, GLenum)> {
+        self.draw_buffer.as_ref().and_then(|db| {
+            db.get_bound_texture_id().map(|id| (id, db.size(), db.color_format()))
+        })
+    }
+
+    /// Whether the draw buffer is antialiased, and thus its color attachment
+    /// must be resolved before being read. `unbind` does so automatically.
+    pub fn needs_resolve(&self) -> bool {
+        match self.draw_buffer {
+            Some(ref db) => db.needs_resolve(),
+            None => false,
+        }
+    }
+
+    /// Copies what was rendered into the multisampled draw buffer to its
+    /// color attachment. The context must be current.
+    pub fn resolve_draw_buffer(&self) {
+        if let Some(ref db) = self.draw_buffer {
+            db.resolve();
+        }
+    }
+
+    /// Reads the draw buffer back into `pixels`, see
+    /// `DrawBuffer::read_pixels_into`. The context must be current.
+    pub fn read_draw_buffer_into(&self, pixels: &mut [u8]) -> Result<(), &'static str> {
+        match self.draw_buffer {
+            Some(ref db) => db.read_pixels_into(pixels),
+            None => Err("No DrawBuffer found"),
+        }
+    }
+
+    /// Inserts a fence after every command issued so far, see `GLFence`. The
+    /// context must be current.
+    pub fn create_fence(&self) -> Result<GLFence, &'static str> {
+        GLFence::new(self)
+    }
+
+    /// Starts reading the draw buffer back without waiting for the GPU, see
+    /// `PendingReadback`. The context must be current.
+    pub fn begin_read_draw_buffer(&self) -> Result<PendingReadback, &'static str> {
+        PendingReadback::new(self)
+    }
+
+    /// Asks the driver whether the GPU was reset since the last call, in
+    /// which case the context is lost and must be recreated. This only
+    /// works for contexts created with `robust_access`, others always
+    /// report `NotLost`. The context must be current.
+    pub fn check_context_lost(&self) -> Result<ContextLossStatus, &'static str> {
+        let get_graphics_reset_status = ["glGetGraphicsResetStatus",
+                                         "glGetGraphicsResetStatusKHR",
+                                         "glGetGraphicsResetStatusARB",
+                                         "glGetGraphicsResetStatusEXT"].iter()
+            .map(|name| Self::get_proc_address(name))
+            .find(|ptr| !ptr.is_null());
+        let get_graphics_reset_status: extern "system" fn() -> GLenum = match get_graphics_reset_status {
+            Some(ptr) => unsafe { mem::transmute(ptr) },
+            None => return Err("glGetGraphicsResetStatus not found"),
+        };
+
+        Ok(match get_graphics_reset_status() {
+            GUILTY_CONTEXT_RESET => ContextLossStatus::GuiltyReset,
+            INNOCENT_CONTEXT_RESET => ContextLossStatus::InnocentReset,
+            UNKNOWN_CONTEXT_RESET => ContextLossStatus::UnknownReset,
+            _ => ContextLossStatus::NotLost,
+        })
+    }
+
+    /// Has the driver call `callback` with each debug message it reports,
+    /// replacing the previous callback, if any. Messages are delivered
+    /// synchronously, on the thread that made the offending call. Debug
+    /// contexts (see `GLContextAttributes::debug`) report much more. The
+    /// context must be current.
+    pub fn set_debug_callback<F>(&mut self, callback: F) -> Result<(), &'static str>
+        where F: Fn(gl::DebugMessage) + 'static
+    {
+        let debug_message_callback = ["glDebugMessageCallback",
+                                      "glDebugMessageCallbackKHR"].iter()
+            .map(|name| Self::get_proc_address(name))
+            .find(|ptr| !ptr.is_null());
+        let debug_message_callback: DebugMessageCallbackFn = match debug_message_callback {
+            Some(ptr) => unsafe { mem::transmute(ptr) },
+            None => return Err("glDebugMessageCallback not found"),
+        };
+
+        let callback: Box<Box<dyn Fn(gl::DebugMessage)>> = Box::new(Box::new(callback));
+        debug_message_callback(debug_callback_trampoline,
+                               &*callback as *const Box<dyn Fn(gl::DebugMessage)> as *const c_void);
+        self.debug_callback = Some(callback);
+
+        self.gl().enable(gl::DEBUG_OUTPUT);
+        self.gl().enable(gl::DEBUG_OUTPUT_SYNCHRONOUS);
+        Ok(())
+    }
+
+    /// Resizes the draw buffer in place: its framebuffer keeps its id, but
+    /// the attachments are reallocated, and their contents are lost.
     pub fn resize(&mut self, size: Size2D<i32>) -> Result<(), &'static str> {
-        if self.draw_buffer.is_some() {
-            let color_attachment_type =
-                self.borrow_draw_buffer().unwrap().color_attachment_type();
-            self.init_offscreen(size, color_attachment_type)
-        } else {
-            Err("No DrawBuffer found")
+        let mut draw_buffer = match self.draw_buffer.take() {
+            Some(draw_buffer) => draw_buffer,
+            None => return Err("No DrawBuffer found"),
+        };
+
+        let result = self.make_current().and_then(|_| draw_buffer.resize(self, size));
+        self.draw_buffer = Some(draw_buffer);
+        result?;
+
+        self.gl().scissor(0, 0, size.width, size.height);
+        self.gl().viewport(0, 0, size.width, size.height);
+
+        Ok(())
+    }
+
+    /// Installs a fresh draw buffer of the given size, and hands back the
+    /// previous one, which stays readable as long as this context is alive.
+    ///
+    /// This allows double-buffering offscreen rendering without recreating
+    /// the context. The new draw buffer is left bound. On failure the old
+    /// one stays installed and bound.
+    ///
+    /// The returned draw buffer deletes its GL objects through whatever
+    /// context is current when it's dropped, so it must be dropped with
+    /// this context current, and before this context is destroyed.
+    pub fn swap_draw_buffer(&mut self, size: Size2D<i32>) -> Result<DrawBuffer, &'static str> {
+        let old_draw_buffer = match self.draw_buffer.take() {
+            Some(draw_buffer) => draw_buffer,
+            None => return Err("No DrawBuffer found"),
+        };
+
+        if let Err(err) = self.init_offscreen(size, old_draw_buffer.color_attachment_type()) {
+            // The failed draw buffer may have been bound before it was
+            // deleted.
+            if self.is_current() {
+                self.gl().bind_framebuffer(gl::FRAMEBUFFER, old_draw_buffer.get_framebuffer());
+            }
+            self.draw_buffer = Some(old_draw_buffer);
+            return Err(err);
         }
+
+        Ok(old_draw_buffer)
     }
 
     pub fn get_extensions(&self) -> Vec<String> {
@@ -229,8 +465,6 @@ impl<Native> GLContext<Native>
 
         debug_assert!(self.is_current());
 
-        self.gl().clear_color(0.0, 0.0, 0.0, 0.0);
-        self.gl().clear(gl::COLOR_BUFFER_BIT | gl::DEPTH_BUFFER_BIT | gl::STENCIL_BUFFER_BIT);
         self.gl().scissor(0, 0, size.width, size.height);
         self.gl().viewport(0, 0, size.width, size.height);
 
@@ -285,6 +519,58 @@ impl GLVersion {
     }
 }
 
+// From GL_KHR_robustness, which gleam doesn't include.
+const GUILTY_CONTEXT_RESET: GLenum = 0x8253;
+const INNOCENT_CONTEXT_RESET: GLenum = 0x8254;
+const UNKNOWN_CONTEXT_RESET: GLenum = 0x8255;
+
+// Neither gleam's bindings nor its wrapper expose glDebugMessageCallback.
+type DebugProc = extern "system" fn(GLenum, GLenum, GLuint, GLenum, GLsizei,
+                                    *const GLchar, *const c_void);
+type DebugMessageCallbackFn = extern "system" fn(DebugProc, *const c_void);
+
+extern "system" fn debug_callback_trampoline(source: GLenum,
+                                             ty: GLenum,
+                                             id: GLuint,
+                                             severity: GLenum,
+                                             _length: GLsizei,
+                                             message: *const GLchar,
+                                             user_param: *const c_void) {
+    let callback = unsafe { &*(user_param as *const Box<dyn Fn(gl::DebugMessage)>) };
+    let message = unsafe { CStr::from_ptr(message) }.to_string_lossy().into_owned();
+    callback(gl::DebugMessage {
+        message: message,
+        source: source,
+        ty: ty,
+        id: id,
+        severity: severity,
+    });
+}
+
+/// The error returned when the driver couldn't allocate a context or its
+/// draw buffer for lack of memory. Unlike other errors, it's worth freeing
+/// some memory and trying again, so callers can match on it:
+///
+/// ```ignore
+/// match context.resize(size) {
+///     Err(OUT_OF_MEMORY) => { /* shrink caches and retry */ }
+///     result => result.unwrap(),
+/// }
+/// ```
+pub const OUT_OF_MEMORY: &'static str = "Out of memory";
+
+/// Whether a context survived, see `GLContext::check_context_lost`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ContextLossStatus {
+    NotLost,
+    /// The reset was caused by this context.
+    GuiltyReset,
+    /// The reset was caused by some other context.
+    InnocentReset,
+    /// The driver can't tell who caused the reset.
+    UnknownReset,
+}
+
 // Dispatches functions to the thread where a NativeGLContext is bound.
 // Right now it's used in the WGL implementation to dispatch functions to the thread
 // where the context we share from is bound. See the WGL implementation for more details.
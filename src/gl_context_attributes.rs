@@ -11,6 +11,62 @@ pub struct GLContextAttributes {
     pub antialias: bool,
     pub premultiplied_alpha: bool,
     pub preserve_drawing_buffer: bool,
+    /// Request a context with robust buffer access (`GL_KHR_robustness` and
+    /// friends), as WebGL requires, and get GPU resets reported through
+    /// `GLContext::check_context_lost`. Ignored with a warning where the
+    /// backend can't provide it.
+    pub robust_access: bool,
+    /// Request that the implementation zero-initializes every resource
+    /// (only available with ANGLE).
+    pub robust_resource_initialization: bool,
+    /// Refuse to create contexts that are known to be much slower than
+    /// usual, like indirect GLX contexts, instead of just warning.
+    pub fail_if_major_performance_caveat: bool,
+    /// Request a debug context, which reports far more problems through
+    /// `GLContext::set_debug_callback`, at some cost in speed.
+    pub debug: bool,
+    /// Request a context that skips error checking, where invalid calls
+    /// are undefined behavior rather than errors. Can't be combined with
+    /// `debug` or `robust_access`. Since `glGetError` doesn't report
+    /// running out of memory anymore, draw buffer creation can't either.
+    pub no_error: bool,
+    /// How the GPU should schedule this context's work against other
+    /// contexts'. Only a hint, and only honored by some EGL drivers.
+    pub priority: ContextPriority,
+    /// Which desktop OpenGL profile to ask for, when it's 3.2 or newer.
+    /// Creation fails if the backend can't provide it.
+    pub profile: GLProfile,
+}
+
+/// Desktop OpenGL profiles. Older versions and OpenGL ES don't have any.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum GLProfile {
+    Core,
+    /// Keeps the deprecated fixed-function API around.
+    Compatibility,
+}
+
+impl Default for GLProfile {
+    fn default() -> GLProfile {
+        GLProfile::Core
+    }
+}
+
+/// Scheduling priority of a context, see `EGL_IMG_context_priority`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ContextPriority {
+    Low,
+    /// What contexts get by default.
+    Medium,
+    High,
+    /// Preempts everything else, see `EGL_NV_context_priority_realtime`.
+    Realtime,
+}
+
+impl Default for ContextPriority {
+    fn default() -> ContextPriority {
+        ContextPriority::Medium
+    }
 }
 
 #[cfg(feature = "serde")]
@@ -18,7 +74,7 @@ impl<'de> Deserialize<'de> for GLContextAttributes {
     fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
         where D: Deserializer<'de>
     {
-        let values = try!(<[_; 6]>::deserialize(deserializer));
+        let (values, priority, profile) = <([_; 11], u8, u8)>::deserialize(deserializer)?;
         Ok(GLContextAttributes {
             alpha: values[0],
             depth: values[1],
@@ -26,6 +82,21 @@ impl<'de> Deserialize<'de> for GLContextAttributes {
             antialias: values[3],
             premultiplied_alpha: values[4],
             preserve_drawing_buffer: values[5],
+            robust_access: values[6],
+            robust_resource_initialization: values[7],
+            fail_if_major_performance_caveat: values[8],
+            debug: values[9],
+            no_error: values[10],
+            priority: match priority {
+                0 => ContextPriority::Low,
+                2 => ContextPriority::High,
+                3 => ContextPriority::Realtime,
+                _ => ContextPriority::Medium,
+            },
+            profile: match profile {
+                1 => GLProfile::Compatibility,
+                _ => GLProfile::Core,
+            },
         })
     }
 }
@@ -38,8 +109,10 @@ impl Serialize for GLContextAttributes {
         let values = [
             self.alpha, self.depth, self.stencil,
             self.antialias, self.premultiplied_alpha, self.preserve_drawing_buffer,
+            self.robust_access, self.robust_resource_initialization,
+            self.fail_if_major_performance_caveat, self.debug, self.no_error,
         ];
-        values.serialize(serializer)
+        (values, self.priority as u8, self.profile as u8).serialize(serializer)
     }
 }
 
@@ -52,14 +125,21 @@ impl GLContextAttributes {
             antialias: false,
             premultiplied_alpha: false,
             preserve_drawing_buffer: false,
+            robust_access: false,
+            robust_resource_initialization: false,
+            fail_if_major_performance_caveat: false,
+            debug: false,
+            no_error: false,
+            priority: ContextPriority::Medium,
+            profile: GLProfile::Core,
         }
     }
 }
 
 impl Default for GLContextAttributes {
-    // FIXME(ecoal95): `antialias` should be true by default
-    //   but we do not support antialising so... We must change it
-    //   when we do. See GLFeature.
+    // FIXME(ecoal95): `antialias` should be true by default, like in WebGL,
+    //   but multisampled draw buffers need to be resolved before reading
+    //   from them, so keep it opt-in until consumers do so.
     fn default() -> GLContextAttributes {
         GLContextAttributes {
             alpha: true,
@@ -67,7 +147,14 @@ impl Default for GLContextAttributes {
             stencil: false,
             antialias: false,
             premultiplied_alpha: true,
-            preserve_drawing_buffer: false
+            preserve_drawing_buffer: false,
+            robust_access: false,
+            robust_resource_initialization: false,
+            fail_if_major_performance_caveat: false,
+            debug: false,
+            no_error: false,
+            priority: ContextPriority::Medium,
+            profile: GLProfile::Core,
         }
     }
 }
@@ -1,8 +1,7 @@
-// use gleam::gl;
+use gleam::gl;
 use gleam::gl::{GLint};
 
-#[allow(unused_imports)]
-use GLFeature;
+use platform::parse_version_string;
 
 /// This is a cross-platform struct, that every GLContext implementation
 /// should have under the field `capabilities`, as a public field
@@ -15,18 +14,32 @@ pub struct GLContextCapabilities {
 }
 
 impl GLContextCapabilities {
-    #[allow(unused_mut)]
-    pub fn detect() -> GLContextCapabilities {
+    /// Detects the capabilities of the current context. They depend on the
+    /// version we got, which is often newer than the one we asked for.
+    pub fn detect(gl_: &dyn gl::Gl, extensions: &[String]) -> GLContextCapabilities {
         let mut capabilities = GLContextCapabilities {
             max_samples: 0,
         };
 
-
-        // FIXME(ecoal95): uncomment me when we have cross-system constants
-        // if GLFeature::is_supported(GLFeature::FramebufferMultisample) {
-            // unsafe { gl::GetIntegerv(gl::MAX_SAMPLES, &mut capabilities.max_samples as *mut GLint); };
-        // }
+        if GLContextCapabilities::supports_multisample(gl_, extensions) {
+            let mut max_samples = [0];
+            unsafe {
+                gl_.get_integer_v(gl::MAX_SAMPLES, &mut max_samples);
+            }
+            capabilities.max_samples = max_samples[0];
+        }
 
         capabilities
     }
+
+    // Multisampled renderbuffers and glBlitFramebuffer, which we need to
+    // resolve them, are core in both OpenGL 3 and OpenGLES 3, and come with
+    // ARB_framebuffer_object on older desktop GL.
+    fn supports_multisample(gl_: &dyn gl::Gl, extensions: &[String]) -> bool {
+        let version = parse_version_string(&gl_.get_string(gl::VERSION));
+        match version {
+            Some(version) if version.major_version() >= 3 => true,
+            _ => extensions.iter().any(|name| name == "GL_ARB_framebuffer_object"),
+        }
+    }
 }
@@ -0,0 +1,94 @@
+use gleam::gl;
+use gleam::gl::types::{GLbitfield, GLenum, GLsync, GLuint64};
+use std::mem;
+use std::rc::Rc;
+use std::time::Duration;
+
+use GLContext;
+use NativeGLContextMethods;
+
+// gleam's client_wait_sync throws away the result, so we load our own.
+type ClientWaitSyncFn = extern "system" fn(GLsync, GLbitfield, GLuint64) -> GLenum;
+
+/// A fence inserted in a context's command stream, which signals once the
+/// GPU has executed every command before it.
+///
+/// Fences live in the share group, so a context sharing with the one that
+/// created the fence can wait for it too, e.g. before sampling a texture
+/// that was rendered to elsewhere. Needs OpenGL or OpenGL ES 3.
+///
+/// Like `DrawBuffer`, it must be dropped with its context (or one sharing
+/// with it) current.
+pub struct GLFence {
+    gl_: Rc<dyn gl::Gl>,
+    client_wait_sync: ClientWaitSyncFn,
+    sync: GLsync,
+}
+
+impl GLFence {
+    /// Inserts a fence in `context`, which must be current, and flushes it
+    /// so that the fence signals eventually.
+    pub fn new<T: NativeGLContextMethods>(context: &GLContext<T>) -> Result<GLFence, &'static str> {
+        if context.api_version()?.major_version() < 3 {
+            return Err("Fences need OpenGL 3 or OpenGL ES 3");
+        }
+
+        let client_wait_sync = GLContext::<T>::get_proc_address("glClientWaitSync");
+        if client_wait_sync.is_null() {
+            return Err("glClientWaitSync not found");
+        }
+
+        let gl_ = context.clone_gl();
+        let sync = gl_.fence_sync(gl::SYNC_GPU_COMMANDS_COMPLETE, 0);
+        if sync.is_null() {
+            return Err("glFenceSync failed");
+        }
+        gl_.flush();
+
+        Ok(GLFence {
+            gl_: gl_,
+            client_wait_sync: unsafe { mem::transmute(client_wait_sync) },
+            sync: sync,
+        })
+    }
+
+    /// The underlying `GLsync`, for embedders with their own GL bindings.
+    #[inline(always)]
+    pub fn sync(&self) -> GLsync {
+        self.sync
+    }
+
+    /// Whether the fence has signaled, without blocking.
+    pub fn is_signaled(&self) -> Result<bool, &'static str> {
+        self.client_wait(0, 0)
+    }
+
+    /// Blocks the calling thread until the fence signals or `timeout`
+    /// expires, and returns whether it signaled.
+    pub fn wait(&self, timeout: Duration) -> Result<bool, &'static str> {
+        let nanos = timeout.as_secs().saturating_mul(1_000_000_000)
+                                     .saturating_add(timeout.subsec_nanos() as u64);
+        self.client_wait(gl::SYNC_FLUSH_COMMANDS_BIT, nanos)
+    }
+
+    /// Makes the GPU wait for the fence before running any command issued
+    /// afterwards through `gl_`, without blocking the calling thread. `gl_`
+    /// belongs to the context that must wait, which has to be current.
+    pub fn wait_on_gpu(&self, gl_: &dyn gl::Gl) {
+        gl_.wait_sync(self.sync, 0, gl::TIMEOUT_IGNORED);
+    }
+
+    fn client_wait(&self, flags: GLbitfield, timeout: GLuint64) -> Result<bool, &'static str> {
+        match (self.client_wait_sync)(self.sync, flags, timeout) {
+            gl::ALREADY_SIGNALED | gl::CONDITION_SATISFIED => Ok(true),
+            gl::TIMEOUT_EXPIRED => Ok(false),
+            _ => Err("glClientWaitSync failed"),
+        }
+    }
+}
+
+impl Drop for GLFence {
+    fn drop(&mut self) {
+        self.gl_.delete_sync(self.sync);
+    }
+}
@@ -13,6 +13,9 @@ pub struct GLFormats {
     pub depth: GLenum,
     pub stencil: GLenum,
     pub packed_depth_stencil: bool,
+    /// Whether textures can be allocated with immutable storage
+    /// (`glTexStorage2D`).
+    pub texture_storage: bool,
 }
 
 impl GLFormats {
@@ -24,6 +27,7 @@ impl GLFormats {
     #[cfg(not(any(target_os="android", target_os="ios")))]
     pub fn detect(attrs: &GLContextAttributes, extensions: &[String], api_version: GLVersion) -> GLFormats {
         let packed_depth_stencil = GLFormats::supports_packed_depth_stencil(&extensions, api_version);
+        let texture_storage = GLFormats::supports_texture_storage(&extensions, api_version);
 
         if attrs.alpha {
             GLFormats {
@@ -34,6 +38,7 @@ impl GLFormats {
                 depth: gl::DEPTH_COMPONENT24,
                 stencil: gl::STENCIL_INDEX8,
                 packed_depth_stencil: packed_depth_stencil,
+                texture_storage: texture_storage,
             }
         } else {
             GLFormats {
@@ -44,6 +49,7 @@ impl GLFormats {
                 depth: gl::DEPTH_COMPONENT24,
                 stencil: gl::STENCIL_INDEX8,
                 packed_depth_stencil: packed_depth_stencil,
+                texture_storage: texture_storage,
             }
         }
     }
@@ -57,6 +63,7 @@ impl GLFormats {
         let has_rgba8 = has_rgb8 || extensions.iter().any(|s| s == "GL_ARM_rgba8");
 
         let packed_depth_stencil = GLFormats::supports_packed_depth_stencil(&extensions, api_version);
+        let texture_storage = GLFormats::supports_texture_storage(&extensions, api_version);
 
         if attrs.alpha {
             GLFormats {
@@ -67,6 +74,7 @@ impl GLFormats {
                 depth: gl::DEPTH_COMPONENT16,
                 stencil: gl::STENCIL_INDEX8,
                 packed_depth_stencil: packed_depth_stencil,
+                texture_storage: texture_storage,
             }
         } else {
             GLFormats {
@@ -77,6 +85,7 @@ impl GLFormats {
                 depth: gl::DEPTH_COMPONENT16,
                 stencil: gl::STENCIL_INDEX8,
                 packed_depth_stencil: packed_depth_stencil,
+                texture_storage: texture_storage,
             }
         }
     }
@@ -92,5 +101,18 @@ impl GLFormats {
         }
         extensions.iter().any(|s| s == "GL_OES_packed_depth_stencil" || s == "GL_EXT_packed_depth_stencil")
     }
+
+    // Immutable texture storage is core since OpenGL 4.2 and OpenGLES 3.0.
+    // Desktop drivers keep advertising the extension in newer versions, so
+    // that's what we look for there.
+    #[cfg(not(any(target_os="android", target_os="ios")))]
+    fn supports_texture_storage(extensions: &[String], _api_version: GLVersion) -> bool {
+        extensions.iter().any(|s| s == "GL_ARB_texture_storage" || s == "GL_EXT_texture_storage")
+    }
+
+    #[cfg(any(target_os="android", target_os="ios"))]
+    fn supports_texture_storage(extensions: &[String], api_version: GLVersion) -> bool {
+        api_version.major_version() >= 3 || extensions.iter().any(|s| s == "GL_EXT_texture_storage")
+    }
 }
 
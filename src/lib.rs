@@ -27,23 +27,35 @@ extern crate libloading;
 extern crate objc;
 
 mod platform;
-pub use platform::{NativeGLContext, NativeGLContextMethods, NativeGLContextHandle};
+pub use platform::{BackendKind, NativeGLContext, NativeGLContextMethods, NativeGLContextHandle};
 
 #[cfg(feature="osmesa")]
-pub use platform::{OSMesaContext, OSMesaContextHandle};
+pub use platform::{OSMesaConfig, OSMesaContext, OSMesaContextHandle};
 
 mod gl_context;
-pub use gl_context::{GLContext, GLContextDispatcher, GLVersion};
+pub use gl_context::{ContextLossStatus, GLContext, GLContextDispatcher, GLVersion, OUT_OF_MEMORY};
+
+mod current_context_guard;
+pub use current_context_guard::CurrentContextGuard;
 
 mod draw_buffer;
 pub use draw_buffer::{DrawBuffer, ColorAttachmentType};
 
+mod pending_readback;
+pub use pending_readback::PendingReadback;
+
+mod recoverable_context;
+pub use recoverable_context::RecoverableContext;
+
 mod gl_context_attributes;
-pub use gl_context_attributes::GLContextAttributes;
+pub use gl_context_attributes::{ContextPriority, GLContextAttributes, GLProfile};
 
 mod gl_context_capabilities;
 pub use gl_context_capabilities::GLContextCapabilities;
 
+mod gl_fence;
+pub use gl_fence::GLFence;
+
 mod gl_feature;
 pub use gl_feature::GLFeature;
 
@@ -0,0 +1,128 @@
+use euclid::Size2D;
+use gleam::gl;
+use gleam::gl::types::{GLsizeiptr, GLuint};
+use std::ptr;
+use std::rc::Rc;
+use std::slice;
+
+use GLContext;
+use GLFence;
+use NativeGLContextMethods;
+
+/// A readback of the draw buffer that was queued into a pixel buffer object,
+/// so that the render thread doesn't have to wait for the GPU. Poll it with
+/// `try_resolve()`.
+///
+/// Like `DrawBuffer`, it must be dropped with its context current.
+pub struct PendingReadback {
+    gl_: Rc<dyn gl::Gl>,
+    buffer: GLuint,
+    fence: GLFence,
+    size: Size2D<i32>,
+}
+
+impl PendingReadback {
+    /// Queues a readback of `context`'s draw buffer, resolving it first if
+    /// it's antialiased. Needs OpenGL or OpenGL ES 3, and the context must
+    /// be current.
+    pub fn new<T: NativeGLContextMethods>(context: &GLContext<T>) -> Result<Self, &'static str> {
+        let draw_buffer = match context.borrow_draw_buffer() {
+            Some(draw_buffer) => draw_buffer,
+            None => return Err("No DrawBuffer found"),
+        };
+
+        if context.api_version()?.major_version() < 3 {
+            return Err("Asynchronous readback needs OpenGL 3 or OpenGL ES 3");
+        }
+
+        let gl_ = context.clone_gl();
+        let size = draw_buffer.size();
+        let length = size.width as usize * size.height as usize * 4;
+
+        draw_buffer.resolve();
+
+        let mut framebuffer = [0];
+        let mut pack_buffer = [0];
+        let mut pack_alignment = [0];
+        unsafe {
+            gl_.get_integer_v(gl::FRAMEBUFFER_BINDING, &mut framebuffer);
+            gl_.get_integer_v(gl::PIXEL_PACK_BUFFER_BINDING, &mut pack_buffer);
+            gl_.get_integer_v(gl::PACK_ALIGNMENT, &mut pack_alignment);
+        }
+        gl_.bind_framebuffer(gl::FRAMEBUFFER, draw_buffer.get_read_framebuffer());
+
+        let buffer = gl_.gen_buffers(1)[0];
+        gl_.bind_buffer(gl::PIXEL_PACK_BUFFER, buffer);
+        gl_.buffer_data_untyped(gl::PIXEL_PACK_BUFFER, length as GLsizeiptr, ptr::null(), gl::STREAM_READ);
+        gl_.pixel_store_i(gl::PACK_ALIGNMENT, 1);
+        unsafe {
+            gl_.read_pixels_into_pbo(0, 0, size.width, size.height, gl::RGBA, gl::UNSIGNED_BYTE);
+        }
+        gl_.pixel_store_i(gl::PACK_ALIGNMENT, pack_alignment[0]);
+        gl_.bind_buffer(gl::PIXEL_PACK_BUFFER, pack_buffer[0] as GLuint);
+        gl_.bind_framebuffer(gl::FRAMEBUFFER, framebuffer[0] as GLuint);
+
+        if cfg!(feature = "check_gl_errors") {
+            let err = gl_.get_error();
+            if err != gl::NO_ERROR {
+                error!("GL error while queueing a readback: {:#x}", err);
+                gl_.delete_buffers(&[buffer]);
+                return Err("GL error while queueing a readback");
+            }
+        }
+
+        let fence = match GLFence::new(context) {
+            Ok(fence) => fence,
+            Err(err) => {
+                gl_.delete_buffers(&[buffer]);
+                return Err(err);
+            }
+        };
+
+        Ok(PendingReadback {
+            gl_: gl_,
+            buffer: buffer,
+            fence: fence,
+            size: size,
+        })
+    }
+
+    #[inline(always)]
+    pub fn size(&self) -> Size2D<i32> {
+        self.size
+    }
+
+    /// Returns the pixels if the GPU is done with them, or `None` without
+    /// blocking otherwise. The pixels are laid out like in
+    /// `DrawBuffer::read_pixels_into`. The context must be current.
+    pub fn try_resolve(&self) -> Result<Option<Vec<u8>>, &'static str> {
+        if !self.fence.is_signaled()? {
+            return Ok(None);
+        }
+
+        let length = self.size.width as usize * self.size.height as usize * 4;
+        let mut pack_buffer = [0];
+        unsafe {
+            self.gl_.get_integer_v(gl::PIXEL_PACK_BUFFER_BINDING, &mut pack_buffer);
+        }
+        self.gl_.bind_buffer(gl::PIXEL_PACK_BUFFER, self.buffer);
+        let data = self.gl_.map_buffer_range(gl::PIXEL_PACK_BUFFER, 0, length as GLsizeiptr,
+                                             gl::MAP_READ_BIT);
+        let result = if data.is_null() {
+            Err("Couldn't map the readback buffer")
+        } else {
+            let pixels = unsafe { slice::from_raw_parts(data as *const u8, length) }.to_vec();
+            self.gl_.unmap_buffer(gl::PIXEL_PACK_BUFFER);
+            Ok(Some(pixels))
+        };
+        self.gl_.bind_buffer(gl::PIXEL_PACK_BUFFER, pack_buffer[0] as GLuint);
+
+        result
+    }
+}
+
+impl Drop for PendingReadback {
+    fn drop(&mut self) {
+        self.gl_.delete_buffers(&[self.buffer]);
+    }
+}
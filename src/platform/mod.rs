@@ -1,11 +1,39 @@
 use gl_context::{GLContextDispatcher, GLVersion};
 use gleam::gl;
+use gleam::gl::types::GLenum;
+use std::ffi::CStr;
+use std::mem;
+use std::os::raw::c_char;
+use ContextPriority;
+use GLProfile;
+use GLContextAttributes;
+
+/// The windowing system API a native context comes from.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum BackendKind {
+    Egl,
+    Glx,
+    Cgl,
+    Wgl,
+    Eagl,
+    OSMesa,
+    /// Platforms we can't create contexts on.
+    Unsupported,
+    /// Implementations outside of this crate.
+    Other,
+}
 
 pub trait NativeGLContextMethods: Sized {
     type Handle;
 
     fn get_proc_address(&str) -> *const ();
 
+    /// Which backend implements this, for callers that need to work around
+    /// backend-specific issues.
+    fn backend() -> BackendKind {
+        BackendKind::Other
+    }
+
     // These are convenient methods to manage handles
     fn current() -> Option<Self>;
     fn current_handle() -> Option<Self::Handle>;
@@ -22,6 +50,24 @@ pub trait NativeGLContextMethods: Sized {
         Self::create_shared(with, api_type, api_version)
     }
 
+    /// Like `create_shared_with_dispatcher`, but also honoring the context
+    /// creation flags of `attributes` (robustness, debug, no error, priority,
+    /// profile). Backends that don't support them create a regular context.
+    fn create_shared_with_attributes(with: Option<&Self::Handle>,
+                                     api_type: &gl::GlType,
+                                     api_version: GLVersion,
+                                     attributes: &GLContextAttributes,
+                                     dispatcher: Option<Box<dyn GLContextDispatcher>>)
+        -> Result<Self, &'static str> {
+        if Self::backend() == BackendKind::OSMesa {
+            // OSMesa contexts always use the compatibility profile.
+            warn_unsupported_attributes(&GLContextAttributes { profile: GLProfile::Core, ..*attributes });
+        } else {
+            warn_unsupported_attributes(attributes);
+        }
+        Self::create_shared_with_dispatcher(with, api_type, api_version, dispatcher)
+    }
+
     fn create_headless(api_type: &gl::GlType, api_version: GLVersion) -> Result<Self, &'static str> {
         Self::create_shared(None, api_type, api_version)
     }
@@ -31,9 +77,76 @@ pub trait NativeGLContextMethods: Sized {
     fn make_current(&self) -> Result<(), &'static str>;
     fn unbind(&self) -> Result<(), &'static str>;
 
+    /// Like `make_current`, but always goes through the driver, even if the
+    /// context already looks current. For when outside code may have bound
+    /// it to another surface, which `is_current` doesn't notice.
+    fn make_current_force(&self) -> Result<(), &'static str> {
+        self.unbind()?;
+        self.make_current()
+    }
+
+    /// The OpenGL or OpenGL ES version of this context as reported by the
+    /// driver, which may be newer than the one requested. The context must
+    /// be current.
+    fn api_version(&self) -> Result<GLVersion, &'static str> {
+        debug_assert!(self.is_current());
+
+        let get_string = Self::get_proc_address("glGetString");
+        if get_string.is_null() {
+            return Err("glGetString not found");
+        }
+
+        let get_string: extern "system" fn(GLenum) -> *const c_char =
+            unsafe { mem::transmute(get_string) };
+        let version = get_string(gl::VERSION);
+        if version.is_null() {
+            return Err("Couldn't query the context version");
+        }
+
+        let version = unsafe { CStr::from_ptr(version) }.to_string_lossy();
+        parse_version_string(&version).ok_or("Couldn't parse the context version")
+    }
+
     /// Just a somewhat dirty hack to special-case the handling of context
     /// unbinding on old OSMesa versions.
-    fn is_osmesa(&self) -> bool { false }
+    fn is_osmesa(&self) -> bool {
+        Self::backend() == BackendKind::OSMesa
+    }
+}
+
+/// Warns about the context creation flags in `attributes` that a backend
+/// without any support for them will ignore.
+pub fn warn_unsupported_attributes(attributes: &GLContextAttributes) {
+    if attributes.robust_access || attributes.robust_resource_initialization {
+        warn!("Robust contexts are not supported on this backend, ignoring");
+    }
+    if attributes.debug {
+        warn!("Debug contexts are not supported on this backend, ignoring");
+    }
+    if attributes.no_error {
+        warn!("No-error contexts are not supported on this backend, ignoring");
+    }
+    if attributes.priority != ContextPriority::Medium {
+        warn!("Context priorities are not supported on this backend, ignoring");
+    }
+    if attributes.profile == GLProfile::Compatibility {
+        warn!("Compatibility profiles are not supported on this backend, ignoring");
+    }
+}
+
+// Parses GL_VERSION strings, which look like "4.6.0 NVIDIA 390.77" on
+// desktop and "OpenGL ES 3.2 Mesa 18.0.5" on mobile.
+pub fn parse_version_string(version: &str) -> Option<GLVersion> {
+    let version = version.split(' ')
+                         .find(|word| word.starts_with(|c: char| c.is_ascii_digit()))
+                         .unwrap_or("");
+    let mut numbers = version.split('.');
+    let major = numbers.next().and_then(|n| n.parse().ok());
+    let minor = numbers.next().and_then(|n| n.parse().ok());
+    match (major, minor) {
+        (Some(major), Some(minor)) => Some(GLVersion::MajorMinor(major, minor)),
+        _ => None,
+    }
 }
 
 #[cfg(all(unix, not(any(target_os = "macos", target_os = "android", target_os = "ios")), feature="x11"))]
@@ -44,12 +157,14 @@ pub use self::with_glx::{NativeGLContext, NativeGLContextHandle};
 #[cfg(feature="osmesa")]
 pub mod with_osmesa;
 #[cfg(feature="osmesa")]
-pub use self::with_osmesa::{OSMesaContext, OSMesaContextHandle};
+pub use self::with_osmesa::{OSMesaConfig, OSMesaContext, OSMesaContextHandle};
 #[cfg(all(unix, not(any(target_os = "macos", target_os = "android")), not(feature="x11")))]
 pub use self::with_osmesa::{OSMesaContext as NativeGLContext, OSMesaContextHandle as NativeGLContextHandle};
 
 
 #[cfg(any(target_os="android", all(target_os="linux", feature = "test_egl_in_linux")))]
+// Only the tests use it on Linux.
+#[cfg_attr(not(target_os="android"), allow(dead_code))]
 pub mod with_egl;
 #[cfg(target_os="android")]
 pub use self::with_egl::{NativeGLContext, NativeGLContextHandle};
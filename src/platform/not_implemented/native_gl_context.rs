@@ -1,5 +1,5 @@
 use gleam::gl;
-use NativeGLContextMethods;
+use {BackendKind, NativeGLContextMethods};
 use GLVersion;
 
 pub struct NativeGLContext;
@@ -8,6 +8,10 @@ pub struct NativeGLContextHandle;
 impl NativeGLContextMethods for NativeGLContext {
     type Handle = NativeGLContextHandle;
 
+    fn backend() -> BackendKind {
+        BackendKind::Unsupported
+    }
+
     fn get_proc_address(_addr: &str) -> *const () {
         0 as *const ()
     }
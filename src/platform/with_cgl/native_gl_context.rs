@@ -8,7 +8,10 @@ use gleam::gl;
 use std::str::FromStr;
 use std::sync::Mutex;
 
-use platform::NativeGLContextMethods;
+use gl_context::GLContextDispatcher;
+use platform::{warn_unsupported_attributes, BackendKind, NativeGLContextMethods};
+use GLContextAttributes;
+use GLProfile;
 use GLVersion;
 
 lazy_static! {
@@ -22,7 +25,9 @@ const kCGLOGLPVersion_Legacy: CGLPixelFormatAttribute = 0x1000;
 #[allow(non_upper_case_globals)]
 const kCGLOGLPVersion_3_2_Core: CGLPixelFormatAttribute = 0x3200;
 
-pub struct NativeGLContextHandle(CGLContextObj);
+/// A CGL context, possibly created outside of this crate, that new contexts
+/// can share with.
+pub struct NativeGLContextHandle(pub CGLContextObj);
 
 unsafe impl Send for NativeGLContextHandle {}
 
@@ -32,6 +37,19 @@ pub struct NativeGLContext {
 }
 
 impl NativeGLContext {
+    /// Wraps a `CGLContextObj` created elsewhere. It isn't released on
+    /// drop, that's up to its creator.
+    ///
+    /// # Safety
+    ///
+    /// The context must be valid, and stay alive as long as the wrapper.
+    pub unsafe fn from_raw_parts(context: CGLContextObj) -> NativeGLContext {
+        NativeGLContext {
+            native_context: context,
+            weak: true,
+        }
+    }
+
     pub fn new(share_context: Option<&CGLContextObj>,
                pixel_format: &CGLPixelFormatObj)
         -> Result<NativeGLContext, &'static str> {
@@ -60,8 +78,9 @@ impl NativeGLContext {
 
 impl Drop for NativeGLContext {
     fn drop(&mut self) {
-        let _ = self.unbind();
+        // Don't unbind contexts we don't own.
         if !self.weak {
+            let _ = self.unbind();
             unsafe {
                 if CGLDestroyContext(self.native_context) != 0 {
                     debug!("CGLDestroyContext returned an error");
@@ -74,6 +93,10 @@ impl Drop for NativeGLContext {
 impl NativeGLContextMethods for NativeGLContext {
     type Handle = NativeGLContextHandle;
 
+    fn backend() -> BackendKind {
+        BackendKind::Cgl
+    }
+
     fn get_proc_address(addr: &str) -> *const () {
         let symbol_name: CFString = FromStr::from_str(addr).unwrap();
         let framework_name: CFString = FromStr::from_str("com.apple.opengl").unwrap();
@@ -158,6 +181,22 @@ impl NativeGLContextMethods for NativeGLContext {
         result
     }
 
+    fn create_shared_with_attributes(with: Option<&Self::Handle>,
+                                     api_type: &gl::GlType,
+                                     api_version: GLVersion,
+                                     attributes: &GLContextAttributes,
+                                     _dispatcher: Option<Box<GLContextDispatcher>>)
+        -> Result<Self, &'static str> {
+        // The legacy profile stops at OpenGL 2.1, anything newer is core.
+        if attributes.profile == GLProfile::Compatibility && api_version.major_version() >= 3 {
+            return Err("CGL doesn't provide compatibility profiles for OpenGL 3 and newer");
+        }
+        // Below OpenGL 3 the legacy profile is the compatibility profile,
+        // so there is nothing to warn about.
+        warn_unsupported_attributes(&GLContextAttributes { profile: GLProfile::Core, ..*attributes });
+        Self::create_shared(with, api_type, api_version)
+    }
+
     fn handle(&self) -> Self::Handle {
         NativeGLContextHandle(self.native_context)
     }
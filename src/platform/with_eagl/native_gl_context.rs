@@ -1,4 +1,4 @@
-use platform::NativeGLContextMethods;
+use platform::{BackendKind, NativeGLContextMethods};
 use GLVersion;
 use gleam::gl;
 use objc::runtime::{BOOL, NO};
@@ -42,6 +42,10 @@ impl Drop for NativeGLContext {
 impl NativeGLContextMethods for NativeGLContext {
     type Handle = Self;
 
+    fn backend() -> BackendKind {
+        BackendKind::Eagl
+    }
+
     fn get_proc_address(addr: &str) -> *const () {
         let framework = match *OPENGLES_FRAMEWORK {
             Some(ref lib) => lib,
@@ -113,6 +117,14 @@ impl NativeGLContextMethods for NativeGLContext {
     }
 
     fn make_current(&self) -> Result<(), &'static str> {
+        if self.is_current() {
+            return Ok(());
+        }
+
+        self.make_current_force()
+    }
+
+    fn make_current_force(&self) -> Result<(), &'static str> {
         let succeeded: BOOL = unsafe {
             msg_send![*EAGLCONTEXT_CLASS, setCurrentContext: self.0]
         };
@@ -1,16 +1,41 @@
 use euclid::Size2D;
-use platform::NativeGLContextMethods;
-use platform::with_egl::utils::{create_pixel_buffer_backed_offscreen_context};
+use platform::{BackendKind, NativeGLContextMethods};
+use platform::with_egl::utils::{create_pixel_buffer_backed_offscreen_context,
+                                create_pixel_buffer_backed_offscreen_context_on_display,
+                                create_surfaceless_offscreen_context, create_drm_node_offscreen_context,
+                                display_extensions, display_version};
+use std::env;
 use std::ffi::CString;
 use std::ops::Deref;
 use egl;
 use egl::types::{EGLint, EGLBoolean, EGLDisplay, EGLSurface, EGLConfig, EGLContext};
 use gleam::gl;
+use gl_context::GLContextDispatcher;
+use ContextPriority;
+use GLContextAttributes;
 use GLVersion;
+use OUT_OF_MEMORY;
 use libloading as lib;
 
+/// Environment variable to load the GL library from a custom name or path,
+/// e.g. for bundled ANGLE builds or sandboxes with non-standard layouts.
+///
+/// Linking EGL and GL statically, with symbols resolved at link time, isn't
+/// supported: the EGL bindings always link against the shared libEGL (see
+/// build.rs), and GL functions are looked up at runtime.
+const GL_LIB_ENV_VAR: &'static str = "OFFSCREEN_GL_CONTEXT_GL_LIBRARY";
+
 lazy_static! {
     static ref GL_LIB: Option<lib::Library>  = {
+       if let Some(name) = env::var_os(GL_LIB_ENV_VAR) {
+           match lib::Library::new(&name) {
+               Ok(lib) => return Some(lib),
+               Err(err) => error!("Couldn't load {:?} from {}: {}", name, GL_LIB_ENV_VAR, err),
+           }
+       }
+
+       // If there's no library to open, we'll resolve everything through
+       // eglGetProcAddress.
        let names = ["libGLESv2.so", "libGL.so", "libGLESv3.so"];
        for name in &names {
            if let Ok(lib) = lib::Library::new(name) {
@@ -21,7 +46,11 @@ lazy_static! {
        None
     };
 }
-pub struct NativeGLContextHandle(pub EGLDisplay, pub EGLSurface);
+/// A handle to an EGL context and the display it lives on.
+///
+/// It can also be built from a context created outside of this crate, in
+/// order to share textures with it.
+pub struct NativeGLContextHandle(pub EGLContext, pub EGLDisplay);
 unsafe impl Send for NativeGLContextHandle {}
 
 pub struct NativeGLContext {
@@ -31,12 +60,57 @@ pub struct NativeGLContext {
     weak: bool,
 }
 
+// From EGL_EXT_create_context_robustness, EGL_KHR_create_context,
+// EGL_KHR_create_context_no_error, EGL_IMG_context_priority,
+// EGL_NV_context_priority_realtime and
+// EGL_ANGLE_robust_resource_initialization, which our bindings don't include.
+const CONTEXT_OPENGL_ROBUST_ACCESS_EXT: EGLint = 0x30BF;
+const CONTEXT_OPENGL_RESET_NOTIFICATION_STRATEGY_EXT: EGLint = 0x3138;
+const ROBUST_RESOURCE_INITIALIZATION_ANGLE: EGLint = 0x3453;
+const CONTEXT_FLAGS_KHR: EGLint = 0x30FC;
+const CONTEXT_OPENGL_DEBUG_BIT_KHR: EGLint = 0x1;
+const CONTEXT_OPENGL_ROBUST_ACCESS_BIT_KHR: EGLint = 0x4;
+const CONTEXT_OPENGL_NO_ERROR_KHR: EGLint = 0x31B3;
+const CONTEXT_PRIORITY_LEVEL_IMG: EGLint = 0x3100;
+const CONTEXT_PRIORITY_HIGH_IMG: EGLint = 0x3101;
+const CONTEXT_PRIORITY_LOW_IMG: EGLint = 0x3103;
+const CONTEXT_PRIORITY_REALTIME_NV: EGLint = 0x3357;
+
 impl NativeGLContext {
+    /// Wraps a context created by someone else, e.g. SDL or a game engine,
+    /// without it having to be current. `surface` is what it gets bound to,
+    /// `egl::NO_SURFACE` for surfaceless contexts. The context stays owned
+    /// by its creator and isn't destroyed on drop.
+    ///
+    /// # Safety
+    ///
+    /// Nothing checks that the handles are valid; they must be, and must
+    /// outlive the wrapper.
+    pub unsafe fn from_raw_parts(display: EGLDisplay,
+                                 context: EGLContext,
+                                 surface: EGLSurface) -> NativeGLContext {
+        NativeGLContext {
+            native_display: display,
+            native_surface: surface,
+            native_context: context,
+            weak: true,
+        }
+    }
+
+    /// The surface this context is made current on, `egl::NO_SURFACE` if
+    /// it's surfaceless.
+    #[inline(always)]
+    pub fn surface(&self) -> EGLSurface {
+        self.native_surface
+    }
+
     pub fn new(share_context: Option<&EGLContext>,
                display: EGLDisplay,
                surface: EGLSurface,
                config: EGLConfig,
-               client_version: u8)
+               api_type: &gl::GlType,
+               client_version: u8,
+               attributes: &GLContextAttributes)
         -> Result<NativeGLContext, &'static str> {
 
         let shared = match share_context {
@@ -44,28 +118,113 @@ impl NativeGLContext {
             None => egl::NO_CONTEXT as EGLContext,
         };
 
-        let attributes = [
+        let mut context_attributes = vec![
             egl::CONTEXT_CLIENT_VERSION as EGLint, client_version as EGLint,
-            egl::NONE as EGLint, 0, 0, 0, // see mod.rs
         ];
 
-        let mut ctx =  unsafe { egl::CreateContext(display, config, shared, attributes.as_ptr()) };
+        if attributes.robust_access || attributes.robust_resource_initialization ||
+           attributes.debug || attributes.no_error ||
+           attributes.priority != ContextPriority::Medium {
+            let extensions = display_extensions(display);
+            // EGL_KHR_create_context is core in EGL 1.5, with its own
+            // attributes instead of the flags.
+            let egl_1_5 = display_version(display) >= (1, 5);
+            let create_context = extensions.split(' ').any(|e| e == "EGL_KHR_create_context");
+            let mut context_flags = 0;
+            if attributes.robust_access && *api_type == gl::GlType::Gl {
+                // EGL_EXT_create_context_robustness only covers OpenGL ES.
+                if egl_1_5 || create_context {
+                    if egl_1_5 {
+                        context_attributes.push(egl::CONTEXT_OPENGL_ROBUST_ACCESS as EGLint);
+                        context_attributes.push(egl::TRUE as EGLint);
+                    } else {
+                        context_flags |= CONTEXT_OPENGL_ROBUST_ACCESS_BIT_KHR;
+                    }
+                    // Same value as the KHR one.
+                    context_attributes.push(egl::CONTEXT_OPENGL_RESET_NOTIFICATION_STRATEGY as EGLint);
+                    context_attributes.push(egl::LOSE_CONTEXT_ON_RESET as EGLint);
+                } else {
+                    warn!("EGL_KHR_create_context not supported, ignoring robust_access");
+                }
+            } else if attributes.robust_access {
+                if extensions.split(' ').any(|e| e == "EGL_EXT_create_context_robustness") {
+                    context_attributes.push(CONTEXT_OPENGL_ROBUST_ACCESS_EXT);
+                    context_attributes.push(egl::TRUE as EGLint);
+                    // Otherwise resets are never reported, see
+                    // GLContext::check_context_lost.
+                    context_attributes.push(CONTEXT_OPENGL_RESET_NOTIFICATION_STRATEGY_EXT);
+                    context_attributes.push(egl::LOSE_CONTEXT_ON_RESET as EGLint);
+                } else {
+                    warn!("EGL_EXT_create_context_robustness not supported, ignoring robust_access");
+                }
+            }
+            if attributes.robust_resource_initialization {
+                if extensions.split(' ').any(|e| e == "EGL_ANGLE_robust_resource_initialization") {
+                    context_attributes.push(ROBUST_RESOURCE_INITIALIZATION_ANGLE);
+                    context_attributes.push(egl::TRUE as EGLint);
+                } else {
+                    warn!("EGL_ANGLE_robust_resource_initialization not supported, ignoring \
+                           robust_resource_initialization");
+                }
+            }
+            if attributes.debug {
+                if create_context {
+                    context_flags |= CONTEXT_OPENGL_DEBUG_BIT_KHR;
+                } else {
+                    warn!("EGL_KHR_create_context not supported, ignoring debug");
+                }
+            }
+            if context_flags != 0 {
+                context_attributes.push(CONTEXT_FLAGS_KHR);
+                context_attributes.push(context_flags);
+            }
+            if attributes.no_error {
+                if extensions.split(' ').any(|e| e == "EGL_KHR_create_context_no_error") {
+                    context_attributes.push(CONTEXT_OPENGL_NO_ERROR_KHR);
+                    context_attributes.push(egl::TRUE as EGLint);
+                } else {
+                    warn!("EGL_KHR_create_context_no_error not supported, ignoring no_error");
+                }
+            }
+            if attributes.priority != ContextPriority::Medium {
+                let (level, extension) = match attributes.priority {
+                    ContextPriority::Low => (CONTEXT_PRIORITY_LOW_IMG, "EGL_IMG_context_priority"),
+                    ContextPriority::High => (CONTEXT_PRIORITY_HIGH_IMG, "EGL_IMG_context_priority"),
+                    _ => (CONTEXT_PRIORITY_REALTIME_NV, "EGL_NV_context_priority_realtime"),
+                };
+                if extensions.split(' ').any(|e| e == extension) {
+                    // The driver may still pick another priority, e.g. if
+                    // the process isn't privileged enough.
+                    context_attributes.push(CONTEXT_PRIORITY_LEVEL_IMG);
+                    context_attributes.push(level);
+                } else {
+                    warn!("{} not supported, ignoring priority", extension);
+                }
+            }
+        }
+
+        context_attributes.extend_from_slice(&[egl::NONE as EGLint, 0, 0, 0]); // see mod.rs
+
+        let mut ctx =  unsafe { egl::CreateContext(display, config, shared, context_attributes.as_ptr()) };
 
         if share_context.is_some() && ctx == (egl::NO_CONTEXT as EGLContext) && client_version != 3 {
             // Workaround for GPUs that don't like different CONTEXT_CLIENT_VERSION value when sharing (e.g. Mali-T880).
             // Set CONTEXT_CLIENT_VERSION 3 to fix the shared ctx creation failure. Note that the ctx is still OpenGL ES 2.0
             // compliant because egl::OPENGL_ES2_BIT is set for egl::RENDERABLE_TYPE. See utils.rs.
-            let attributes = [
-                egl::CONTEXT_CLIENT_VERSION as EGLint, 3,
-                egl::NONE as EGLint, 0, 0, 0, // see mod.rs
-            ];
-            ctx =  unsafe { egl::CreateContext(display, config, shared, attributes.as_ptr()) };
+            context_attributes[1] = 3;
+            ctx =  unsafe { egl::CreateContext(display, config, shared, context_attributes.as_ptr()) };
         }
 
         // TODO: Check for every type of error possible, not just client error?
         // Note if we do it we must do it too on egl::CreatePBufferSurface, etc...
         if ctx == (egl::NO_CONTEXT as EGLContext) {
-            unsafe { egl::DestroySurface(display, surface) };
+            let err = unsafe { egl::GetError() };
+            if surface != (egl::NO_SURFACE as EGLSurface) {
+                unsafe { egl::DestroySurface(display, surface) };
+            }
+            if err == egl::BAD_ALLOC as EGLint {
+                return Err(OUT_OF_MEMORY);
+            }
             return Err("Error creating an EGL context");
         }
 
@@ -76,14 +235,50 @@ impl NativeGLContext {
             weak: false,
         })
     }
+
+    /// Creates a headless context on `display` rather than on the default
+    /// display, e.g. a platform display for a specific GPU, or one that
+    /// another EGL user already initialized.
+    pub fn create_headless_on_display(display: EGLDisplay,
+                                      api_type: &gl::GlType,
+                                      api_version: GLVersion,
+                                      attributes: &GLContextAttributes)
+        -> Result<NativeGLContext, &'static str> {
+        create_pixel_buffer_backed_offscreen_context_on_display(Size2D::new(16, 16), display,
+                                                                api_type, api_version, attributes)
+    }
+
+    /// Creates a context on the `EGL_MESA_platform_surfaceless` platform,
+    /// with no pbuffer behind it. Handy for headless machines and
+    /// containers, where there's neither a window system nor a GPU display.
+    pub fn create_surfaceless(api_type: &gl::GlType,
+                              api_version: GLVersion,
+                              attributes: &GLContextAttributes)
+        -> Result<NativeGLContext, &'static str> {
+        create_surfaceless_offscreen_context(api_type, api_version, attributes)
+    }
+
+    /// Creates a headless context on the GPU whose DRM primary or render
+    /// node is `path` (e.g. `/dev/dri/renderD128`), for multi-GPU machines
+    /// where it must match a device another API already picked.
+    pub fn create_headless_on_drm_node(path: &str,
+                                       api_type: &gl::GlType,
+                                       api_version: GLVersion,
+                                       attributes: &GLContextAttributes)
+        -> Result<NativeGLContext, &'static str> {
+        create_drm_node_offscreen_context(path, api_type, api_version, attributes)
+    }
 }
 
 impl Drop for NativeGLContext {
     fn drop(&mut self) {
-        let _ = self.unbind();
+        // Weak contexts are borrowed from whoever made them current, so we
+        // must neither destroy them nor unbind them.
         if !self.weak {
+            let _ = self.unbind();
             unsafe {
-                if egl::DestroySurface(self.native_display, self.native_surface) == 0 {
+                if self.native_surface != (egl::NO_SURFACE as EGLSurface) &&
+                   egl::DestroySurface(self.native_display, self.native_surface) == 0 {
                     debug!("egl::DestroySurface failed");
                 }
                 if egl::DestroyContext(self.native_display, self.native_context) == 0 {
@@ -97,6 +292,10 @@ impl Drop for NativeGLContext {
 impl NativeGLContextMethods for NativeGLContext {
     type Handle = NativeGLContextHandle;
 
+    fn backend() -> BackendKind {
+        BackendKind::Egl
+    }
+
     // According to the EGL spec <= 1.4, eglGetProcAddress should only be used to
     // retrieve extension functions. Some implementatios return NULL for core OpenGL functions.
     // Other implementations may return non-NULL values even for invalid core or extension symbols.
@@ -120,13 +319,25 @@ impl NativeGLContextMethods for NativeGLContext {
     fn create_headless(api_type: &gl::GlType, api_version: GLVersion) -> Result<NativeGLContext, &'static str> {
         // We create a context with a dummy size, we can't rely on a
         // default framebuffer
-        create_pixel_buffer_backed_offscreen_context(Size2D::new(16, 16), None, api_type, api_version)
+        create_pixel_buffer_backed_offscreen_context(Size2D::new(16, 16), None, api_type, api_version,
+                                                     &GLContextAttributes::any())
     }
 
     fn create_shared(with: Option<&Self::Handle>,
                      api_type: &gl::GlType,
                      api_version: GLVersion) -> Result<NativeGLContext, &'static str> {
-        create_pixel_buffer_backed_offscreen_context(Size2D::new(16, 16), with, api_type, api_version)
+        create_pixel_buffer_backed_offscreen_context(Size2D::new(16, 16), with, api_type, api_version,
+                                                     &GLContextAttributes::any())
+    }
+
+    fn create_shared_with_attributes(with: Option<&Self::Handle>,
+                                     api_type: &gl::GlType,
+                                     api_version: GLVersion,
+                                     attributes: &GLContextAttributes,
+                                     _dispatcher: Option<Box<dyn GLContextDispatcher>>)
+        -> Result<NativeGLContext, &'static str> {
+        create_pixel_buffer_backed_offscreen_context(Size2D::new(16, 16), with, api_type, api_version,
+                                                     attributes)
     }
 
     fn current_handle() -> Option<Self::Handle> {
@@ -143,10 +354,9 @@ impl NativeGLContextMethods for NativeGLContext {
 
     fn current() -> Option<Self> {
         if let Some(handle) = Self::current_handle() {
+            // This is `egl::NO_SURFACE` for surfaceless contexts.
             let surface = unsafe { egl::GetCurrentSurface(egl::DRAW as EGLint) };
 
-            debug_assert!(surface != egl::NO_SURFACE);
-
             Some(NativeGLContext {
                 native_context: handle.0,
                 native_display: handle.1,
@@ -1,12 +1,144 @@
+use std::ffi::{CStr, CString};
 use std::mem;
+use std::os::raw::{c_char, c_void};
+use std::ptr;
 use euclid::Size2D;
 use super::{NativeGLContext, NativeGLContextHandle};
 
+use platform::parse_version_string;
+use GLContextAttributes;
 use GLVersion;
+use OUT_OF_MEMORY;
 use egl;
-use egl::types::{EGLNativeDisplayType, EGLDisplay, EGLConfig, EGLSurface, EGLint};
+use egl::types::{EGLBoolean, EGLNativeDisplayType, EGLDisplay, EGLConfig, EGLContext, EGLSurface};
+use egl::types::{EGLenum, EGLint};
 use gleam::gl;
 
+// From EGL_MESA_platform_surfaceless, EGL_EXT_platform_device,
+// EGL_EXT_device_drm and EGL_EXT_device_drm_render_node, which our bindings
+// don't include.
+const PLATFORM_SURFACELESS_MESA: EGLenum = 0x31DD;
+const PLATFORM_DEVICE_EXT: EGLenum = 0x313F;
+const DRM_DEVICE_FILE_EXT: EGLint = 0x3233;
+const DRM_RENDER_NODE_FILE_EXT: EGLint = 0x3377;
+
+type EGLDeviceEXT = *mut c_void;
+type GetPlatformDisplayEXTFn = extern "system" fn(EGLenum, *mut c_void, *const EGLint) -> EGLDisplay;
+type QueryDevicesEXTFn = extern "system" fn(EGLint, *mut EGLDeviceEXT, *mut EGLint) -> EGLBoolean;
+type QueryDeviceStringEXTFn = extern "system" fn(EGLDeviceEXT, EGLint) -> *const c_char;
+
+/// Returns the extensions of `display`, or the client extensions if it's
+/// `egl::NO_DISPLAY`.
+pub fn display_extensions(display: EGLDisplay) -> String {
+    unsafe {
+        let extensions = egl::QueryString(display, egl::EXTENSIONS as EGLint);
+        if extensions.is_null() {
+            return String::new();
+        }
+        CStr::from_ptr(extensions).to_string_lossy().into_owned()
+    }
+}
+
+/// Returns the EGL version of an initialized `display`, e.g. `(1, 5)`.
+pub fn display_version(display: EGLDisplay) -> (u8, u8) {
+    let version = unsafe {
+        let version = egl::QueryString(display, egl::VERSION as EGLint);
+        if version.is_null() {
+            return (1, 0);
+        }
+        CStr::from_ptr(version).to_string_lossy().into_owned()
+    };
+    match parse_version_string(&version) {
+        Some(GLVersion::MajorMinor(major, minor)) => (major, minor),
+        _ => (1, 0),
+    }
+}
+
+lazy_static! {
+    // Looking it up goes through eglGetPlatformDisplayEXT, and always gives
+    // the same display, so it's only done once. Raw pointers aren't Sync,
+    // hence the address.
+    static ref SURFACELESS_DISPLAY: Option<usize> = find_surfaceless_display().map(|d| d as usize);
+}
+
+// Returns the display of the surfaceless platform, if the EGL implementation
+// has one.
+fn surfaceless_display() -> Option<EGLDisplay> {
+    SURFACELESS_DISPLAY.map(|display| display as EGLDisplay)
+}
+
+fn find_surfaceless_display() -> Option<EGLDisplay> {
+    let client_extensions = display_extensions(egl::NO_DISPLAY as EGLDisplay);
+    let has_extension = |name| client_extensions.split(' ').any(|e| e == name);
+    if !has_extension("EGL_EXT_platform_base") || !has_extension("EGL_MESA_platform_surfaceless") {
+        return None;
+    }
+
+    // Go through the extension entry point, eglGetPlatformDisplay is EGL 1.5
+    // only, and linking to it would fail on older implementations.
+    let name = CString::new("eglGetPlatformDisplayEXT").unwrap();
+    let get_platform_display = unsafe { egl::GetProcAddress(name.as_ptr()) };
+    if get_platform_display.is_null() {
+        return None;
+    }
+
+    let get_platform_display: GetPlatformDisplayEXTFn = unsafe { mem::transmute(get_platform_display) };
+    let display = get_platform_display(PLATFORM_SURFACELESS_MESA, ptr::null_mut(), ptr::null());
+    if display == (egl::NO_DISPLAY as EGLDisplay) {
+        None
+    } else {
+        Some(display)
+    }
+}
+
+// Returns the display of the GPU whose DRM primary or render node is `path`,
+// using EGL_EXT_device_enumeration and EGL_EXT_platform_device.
+fn drm_node_display(path: &str) -> Result<EGLDisplay, &'static str> {
+    let client_extensions = display_extensions(egl::NO_DISPLAY as EGLDisplay);
+    let has_extension = |name| client_extensions.split(' ').any(|e| e == name);
+    if !has_extension("EGL_EXT_device_enumeration") || !has_extension("EGL_EXT_platform_device") {
+        return Err("EGL_EXT_device_enumeration or EGL_EXT_platform_device not supported");
+    }
+
+    let get_proc_address = |name: &str| {
+        let name = CString::new(name).unwrap();
+        unsafe { egl::GetProcAddress(name.as_ptr()) }
+    };
+    let query_devices = get_proc_address("eglQueryDevicesEXT");
+    let query_device_string = get_proc_address("eglQueryDeviceStringEXT");
+    let get_platform_display = get_proc_address("eglGetPlatformDisplayEXT");
+    if query_devices.is_null() || query_device_string.is_null() || get_platform_display.is_null() {
+        return Err("EGL device functions not found");
+    }
+
+    let query_devices: QueryDevicesEXTFn = unsafe { mem::transmute(query_devices) };
+    let query_device_string: QueryDeviceStringEXTFn = unsafe { mem::transmute(query_device_string) };
+    let get_platform_display: GetPlatformDisplayEXTFn = unsafe { mem::transmute(get_platform_display) };
+
+    let mut count = 0;
+    if query_devices(0, ptr::null_mut(), &mut count) == 0 {
+        return Err("eglQueryDevicesEXT");
+    }
+    let mut devices = vec![ptr::null_mut(); count as usize];
+    if query_devices(count, devices.as_mut_ptr(), &mut count) == 0 {
+        return Err("eglQueryDevicesEXT");
+    }
+
+    // Devices without EGL_EXT_device_drm (e.g. software ones) just return
+    // null here.
+    let device = devices.into_iter().take(count as usize).find(|&device| {
+        [DRM_DEVICE_FILE_EXT, DRM_RENDER_NODE_FILE_EXT].iter().any(|&name| {
+            let file = query_device_string(device, name);
+            !file.is_null() && unsafe { CStr::from_ptr(file) }.to_bytes() == path.as_bytes()
+        })
+    });
+
+    match device {
+        Some(device) => Ok(get_platform_display(PLATFORM_DEVICE_EXT, device, ptr::null())),
+        None => Err("No EGL device found for that DRM node"),
+    }
+}
+
 fn create_pbuffer_surface(display: EGLDisplay, config: EGLConfig, size: Size2D<i32>) -> Result<EGLSurface, &'static str> {
     let mut attrs = [
         egl::WIDTH as EGLint, size.width as EGLint,
@@ -17,6 +149,9 @@ fn create_pbuffer_surface(display: EGLDisplay, config: EGLConfig, size: Size2D<i
     let surface = unsafe { egl::CreatePbufferSurface(display, config, &mut *attrs.as_mut_ptr()) };
 
     if surface == (egl::NO_SURFACE as EGLSurface) {
+        if unsafe { egl::GetError() } == egl::BAD_ALLOC as EGLint {
+            return Err(OUT_OF_MEMORY);
+        }
         return Err("egl::CreatePBufferSurface");
     }
 
@@ -26,7 +161,90 @@ fn create_pbuffer_surface(display: EGLDisplay, config: EGLConfig, size: Size2D<i
 pub fn create_pixel_buffer_backed_offscreen_context(size: Size2D<i32>,
                                                     shared_with: Option<&NativeGLContextHandle>,
                                                     api_type: &gl::GlType,
-                                                    api_version: GLVersion) -> Result<NativeGLContext, &'static str>  {
+                                                    api_version: GLVersion,
+                                                    attributes: &GLContextAttributes)
+                                                    -> Result<NativeGLContext, &'static str>  {
+    let (shared_with, display) = match shared_with {
+        Some(handle) => (Some(&handle.0), handle.1),
+        None => {
+            let display = unsafe { egl::GetDisplay(egl::DEFAULT_DISPLAY as EGLNativeDisplayType) };
+            (None, initialize_display(display)?)
+        }
+    };
+
+    // Contexts on the surfaceless platform, including ones sharing with
+    // them, don't get a pbuffer.
+    let surfaceless = surfaceless_display() == Some(display);
+    create_context_on_display(size, display, surfaceless, shared_with, api_type, api_version, attributes)
+}
+
+/// Like `create_pixel_buffer_backed_offscreen_context`, but on the given
+/// display instead of the default one.
+pub fn create_pixel_buffer_backed_offscreen_context_on_display(size: Size2D<i32>,
+                                                               display: EGLDisplay,
+                                                               api_type: &gl::GlType,
+                                                               api_version: GLVersion,
+                                                               attributes: &GLContextAttributes)
+                                                               -> Result<NativeGLContext, &'static str> {
+    let display = initialize_display(display)?;
+    let surfaceless = surfaceless_display() == Some(display);
+    create_context_on_display(size, display, surfaceless, None, api_type, api_version, attributes)
+}
+
+/// Creates a context on the surfaceless platform. It has no surface at all,
+/// so everything has to be rendered into framebuffer objects, which avoids
+/// the pbuffer size limits of some drivers.
+pub fn create_surfaceless_offscreen_context(api_type: &gl::GlType,
+                                            api_version: GLVersion,
+                                            attributes: &GLContextAttributes)
+                                            -> Result<NativeGLContext, &'static str> {
+    let display = match surfaceless_display() {
+        Some(display) => display,
+        None => return Err("EGL_MESA_platform_surfaceless not supported"),
+    };
+    let display = initialize_display(display)?;
+    create_context_on_display(Size2D::new(16, 16), display, true, None, api_type, api_version, attributes)
+}
+
+/// Creates a context on the GPU behind the DRM node at `path`, so that it
+/// lands on the same GPU as e.g. a Vulkan device that was opened on it.
+pub fn create_drm_node_offscreen_context(path: &str,
+                                         api_type: &gl::GlType,
+                                         api_version: GLVersion,
+                                         attributes: &GLContextAttributes)
+                                         -> Result<NativeGLContext, &'static str> {
+    let display = drm_node_display(path)?;
+    create_pixel_buffer_backed_offscreen_context_on_display(Size2D::new(16, 16), display,
+                                                            api_type, api_version, attributes)
+}
+
+fn initialize_display(display: EGLDisplay) -> Result<EGLDisplay, &'static str> {
+    if display == (egl::NO_DISPLAY as EGLDisplay) {
+        return Err("egl::GetDisplay");
+    }
+
+    // TODO: Ensure this is correct. It seems it's refcounted, but not atomically, so
+    // we can't `Terminate` it on drop.
+    //
+    // Initializing a display twice is harmless, so this is also fine for
+    // displays someone else already initialized.
+    if unsafe { egl::Initialize(display, 0 as *mut _, 0 as *mut _) } == 0 {
+        return Err("egl::Initialize");
+    }
+
+    Ok(display)
+}
+
+// Contexts on the surfaceless platform render into framebuffer objects only,
+// and don't get a pbuffer.
+fn create_context_on_display(size: Size2D<i32>,
+                             display: EGLDisplay,
+                             surfaceless: bool,
+                             shared_with: Option<&EGLContext>,
+                             api_type: &gl::GlType,
+                             api_version: GLVersion,
+                             context_attributes: &GLContextAttributes)
+                             -> Result<NativeGLContext, &'static str> {
     let client_version = api_version.major_version();
     let renderable_type = match *api_type {
         gl::GlType::Gl => {
@@ -43,8 +261,14 @@ pub fn create_pixel_buffer_backed_offscreen_context(size: Size2D<i32>,
         },
     };
 
+    if surfaceless &&
+       !display_extensions(display).split(' ').any(|e| e == "EGL_KHR_surfaceless_context") {
+        return Err("EGL_KHR_surfaceless_context not supported");
+    }
+    let surface_type = if surfaceless { 0 } else { egl::PBUFFER_BIT };
+
     let attributes = [
-        egl::SURFACE_TYPE as EGLint, egl::PBUFFER_BIT as EGLint,
+        egl::SURFACE_TYPE as EGLint, surface_type as EGLint,
         egl::RENDERABLE_TYPE as EGLint, renderable_type as EGLint,
         egl::RED_SIZE as EGLint, 8,
         egl::GREEN_SIZE as EGLint, 8,
@@ -53,34 +277,6 @@ pub fn create_pixel_buffer_backed_offscreen_context(size: Size2D<i32>,
         egl::NONE as EGLint, 0, 0, 0, // see mod.rs
     ];
 
-    let (shared_with, display) = match shared_with {
-        Some(handle) => (Some(&handle.0), handle.1),
-        None => {
-            unsafe {
-                let display = egl::GetDisplay(egl::DEFAULT_DISPLAY as EGLNativeDisplayType);
-
-                if display == (egl::NO_DISPLAY as EGLDisplay) {
-                    return Err("egl::GetDisplay");
-                }
-
-                // TODO: Ensure this is correct. It seems it's refcounted, but not atomically, so
-                // we can't `Terminate` it on drop.
-                //
-                // It's the default display anyways so it is not a big problem.
-                if egl::Initialize(display, 0 as *mut _, 0 as *mut _) == 0 {
-                    return Err("egl::Initialize");
-                }
-
-                (None, display)
-            }
-        }
-    };
-
-
-    if display == (egl::NO_DISPLAY as EGLDisplay) {
-        return Err("egl::GetDisplay");
-    }
-
     let mut config : EGLConfig = unsafe { mem::uninitialized() };
     let mut found_configs : EGLint = 0;
 
@@ -98,7 +294,11 @@ pub fn create_pixel_buffer_backed_offscreen_context(size: Size2D<i32>,
         return Err("No EGL config for pBuffer");
     }
 
-    let surface = try!(create_pbuffer_surface(display, config, size));
+    let surface = if surfaceless {
+        egl::NO_SURFACE as EGLSurface
+    } else {
+        create_pbuffer_surface(display, config, size)?
+    };
 
-    NativeGLContext::new(shared_with, display, surface, config, client_version)
+    NativeGLContext::new(shared_with, display, surface, config, api_type, client_version, context_attributes)
 }
@@ -1,6 +1,9 @@
 use std::ffi::CString;
 
-use gl_context::GLVersion;
+use gl_context::{GLContextDispatcher, GLVersion};
+use ContextPriority;
+use GLContextAttributes;
+use GLProfile;
 use gleam::gl;
 use glx;
 use glx_extra;
@@ -9,8 +12,13 @@ use glx::types::{GLXContext, GLXDrawable, GLXFBConfig, GLXPixmap};
 use euclid::Size2D;
 use super::utils::{create_offscreen_pixmap_backed_context};
 
-use platform::NativeGLContextMethods;
+use platform::{BackendKind, NativeGLContextMethods};
 
+// From GLX_ARB_create_context_no_error, which our bindings don't include.
+const CONTEXT_OPENGL_NO_ERROR_ARB: c_int = 0x31B3;
+
+/// A GLX context and its X display. Building one out of a foreign context
+/// (e.g. the host engine's) lets new contexts share its textures.
 pub struct NativeGLContextHandle(pub GLXContext, pub *mut glx::types::Display);
 
 unsafe impl Send for NativeGLContextHandle {}
@@ -23,12 +31,32 @@ pub struct NativeGLContext {
 }
 
 impl NativeGLContext {
+    /// Wraps a context some other library created, and binds to `drawable`
+    /// when made current. It's left alone on drop: whoever created it still
+    /// has to destroy it.
+    ///
+    /// # Safety
+    ///
+    /// `display`, `context` and `drawable` must be valid, and stay valid
+    /// for as long as the wrapper lives.
+    pub unsafe fn from_raw_parts(display: *mut glx::types::Display,
+                                 context: GLXContext,
+                                 drawable: GLXDrawable) -> NativeGLContext {
+        NativeGLContext {
+            native_context: context,
+            native_display: display,
+            native_drawable: drawable,
+            weak: true,
+        }
+    }
+
     pub fn new(share_context: Option<&GLXContext>,
                api_version: GLVersion,
                display: *mut glx::types::Display,
                drawable: GLXDrawable,
                framebuffer_config: GLXFBConfig,
-               extensions: String)
+               extensions: String,
+               context_attributes: &GLContextAttributes)
         -> Result<NativeGLContext, &'static str> {
 
         let shared = match share_context {
@@ -36,18 +64,71 @@ impl NativeGLContext {
             None      => 0 as GLXContext,
         };
 
+        if context_attributes.priority != ContextPriority::Medium {
+            warn!("GLX can't provide context priorities, ignoring");
+        }
+
         let native =  if extensions.split(' ').find(|&i| i == "GLX_ARB_create_context").is_some() {
             let (major, minor) = match api_version {
                 GLVersion::Major(major) => { (major, 1) }, // OpenGL 2.1, 3.1
                 GLVersion::MajorMinor(major, minor) => { (major, minor) }
             };
 
-            let attributes = [
+            let mut attributes = vec![
                 glx_extra::CONTEXT_MAJOR_VERSION_ARB as c_int, major as c_int,
                 glx_extra::CONTEXT_MINOR_VERSION_ARB as c_int, minor as c_int,
-                0
             ];
 
+            // Profiles only exist since OpenGL 3.2.
+            if major > 3 || (major == 3 && minor >= 2) {
+                if extensions.split(' ').any(|i| i == "GLX_ARB_create_context_profile") {
+                    let profile = match context_attributes.profile {
+                        GLProfile::Core => glx_extra::CONTEXT_CORE_PROFILE_BIT_ARB,
+                        GLProfile::Compatibility => glx_extra::CONTEXT_COMPATIBILITY_PROFILE_BIT_ARB,
+                    };
+                    attributes.push(glx_extra::CONTEXT_PROFILE_MASK_ARB as c_int);
+                    attributes.push(profile as c_int);
+                } else if context_attributes.profile == GLProfile::Compatibility {
+                    // Without the extension we'd get a core profile.
+                    return Err("Compatibility profile not supported");
+                }
+            }
+
+            let mut flags = if context_attributes.debug {
+                glx_extra::CONTEXT_DEBUG_BIT_ARB
+            } else {
+                0
+            };
+
+            if context_attributes.robust_access {
+                if extensions.split(' ').any(|i| i == "GLX_ARB_create_context_robustness") {
+                    flags |= glx_extra::CONTEXT_ROBUST_ACCESS_BIT_ARB;
+                    attributes.push(glx_extra::CONTEXT_RESET_NOTIFICATION_STRATEGY_ARB as c_int);
+                    attributes.push(glx_extra::LOSE_CONTEXT_ON_RESET_ARB as c_int);
+                } else {
+                    warn!("GLX_ARB_create_context_robustness not supported, ignoring robust_access");
+                }
+            }
+            if context_attributes.robust_resource_initialization {
+                warn!("GLX can't provide robust resource initialization, ignoring");
+            }
+
+            if context_attributes.no_error {
+                if extensions.split(' ').any(|i| i == "GLX_ARB_create_context_no_error") {
+                    attributes.push(CONTEXT_OPENGL_NO_ERROR_ARB);
+                    attributes.push(1);
+                } else {
+                    warn!("GLX_ARB_create_context_no_error not supported, ignoring no_error");
+                }
+            }
+
+            if flags != 0 {
+                attributes.push(glx_extra::CONTEXT_FLAGS_ARB as c_int);
+                attributes.push(flags as c_int);
+            }
+
+            attributes.push(0);
+
             // load the extra GLX functions
             let extra_functions = glx_extra::Glx::load_with(|s| {
                 let c_str = CString::new(s.as_bytes()).unwrap();
@@ -61,6 +142,12 @@ impl NativeGLContext {
                                                         attributes.as_ptr())
             }
         } else {
+             if context_attributes.robust_access || context_attributes.robust_resource_initialization {
+                 warn!("GLX_ARB_create_context not supported, ignoring robustness attributes");
+             }
+             if context_attributes.debug || context_attributes.no_error {
+                 warn!("GLX_ARB_create_context not supported, ignoring debug and no_error");
+             }
              unsafe { 
                  glx::CreateNewContext(display,
                                        framebuffer_config,
@@ -75,17 +162,42 @@ impl NativeGLContext {
             return Err("Error creating native glx context");
         }
 
-        Ok(NativeGLContext {
+        let context = NativeGLContext {
             native_context: native,
             native_display: display,
             native_drawable: drawable,
             weak: false,
-        })
+        };
+
+        // We always ask for a direct context, but the server may give us an
+        // indirect one anyway, e.g. over a forwarded X connection. Those are
+        // capped to OpenGL 1.4, which breaks most consumers in odd ways.
+        // Dropping the context destroys it along with the pixmap.
+        if !context.is_direct() {
+            if context_attributes.fail_if_major_performance_caveat {
+                return Err("Got an indirect GLX context");
+            }
+            warn!("Got an indirect GLX context, expect OpenGL 1.4 at most");
+        }
+
+        Ok(context)
     }
 
     pub fn as_native_glx_context(&self) -> GLXContext {
         self.native_context
     }
+
+    /// Whether this is a direct rendering context. Indirect contexts send
+    /// every call through the X server.
+    pub fn is_direct(&self) -> bool {
+        unsafe { glx::IsDirect(self.native_display, self.native_context) != 0 }
+    }
+
+    /// The drawable this context is made current on.
+    #[inline(always)]
+    pub fn drawable(&self) -> GLXDrawable {
+        self.native_drawable
+    }
 }
 
 impl Drop for NativeGLContext {
@@ -105,6 +217,10 @@ impl Drop for NativeGLContext {
 impl NativeGLContextMethods for NativeGLContext {
     type Handle = NativeGLContextHandle;
 
+    fn backend() -> BackendKind {
+        BackendKind::Glx
+    }
+
     fn get_proc_address(addr: &str) -> *const () {
         let addr = CString::new(addr.as_bytes()).unwrap();
         let addr = addr.as_ptr();
@@ -142,7 +258,18 @@ impl NativeGLContextMethods for NativeGLContext {
     fn create_shared(with: Option<&Self::Handle>,
                      api_type: &gl::GlType,
                      api_version: GLVersion) -> Result<NativeGLContext, &'static str> {
-        create_offscreen_pixmap_backed_context(Size2D::new(16, 16), with, api_type, api_version)
+        create_offscreen_pixmap_backed_context(Size2D::new(16, 16), with, api_type, api_version,
+                                               &GLContextAttributes::any())
+    }
+
+    fn create_shared_with_attributes(with: Option<&Self::Handle>,
+                                     api_type: &gl::GlType,
+                                     api_version: GLVersion,
+                                     attributes: &GLContextAttributes,
+                                     _dispatcher: Option<Box<dyn GLContextDispatcher>>)
+        -> Result<NativeGLContext, &'static str> {
+        create_offscreen_pixmap_backed_context(Size2D::new(16, 16), with, api_type, api_version,
+                                               attributes)
     }
 
     #[inline(always)]
@@ -6,6 +6,7 @@ use std::ffi::CStr;
 use std::os::raw::*;
 use euclid::Size2D;
 
+use GLContextAttributes;
 use GLVersion;
 use NativeGLContext;
 use NativeGLContextHandle;
@@ -55,7 +56,9 @@ unsafe fn get_visual_and_depth(s: *mut Screen, id: VisualID) -> Result<(*mut Vis
 pub fn create_offscreen_pixmap_backed_context(size: Size2D<i32>,
                                               shared_with: Option<&NativeGLContextHandle>,
                                               api_type: &gl::GlType,
-                                              api_version: GLVersion) -> Result<NativeGLContext, &'static str> {
+                                              api_version: GLVersion,
+                                              context_attributes: &GLContextAttributes)
+                                              -> Result<NativeGLContext, &'static str> {
     match *api_type {
         gl::GlType::Gles => {
             return Err("OpenGL ES is not supported");
@@ -165,6 +168,7 @@ pub fn create_offscreen_pixmap_backed_context(size: Size2D<i32>,
 
         let chosen_config = *configs.as_ptr().offset(config_index);
 
-        NativeGLContext::new(shared_with, api_version, dpy, glx_pixmap as GLXDrawable, chosen_config, extensions)
+        NativeGLContext::new(shared_with, api_version, dpy, glx_pixmap as GLXDrawable, chosen_config, extensions,
+                             context_attributes)
     }
 }
@@ -1,22 +1,67 @@
+use euclid::Size2D;
 use std::ffi::CString;
-use std::os::raw::c_int;
+use std::os::raw::{c_int, c_uint};
 use std::ptr;
 
 use osmesa_sys;
 use gl_context::GLVersion;
 use gleam::gl;
 
-use platform::NativeGLContextMethods;
+use platform::{BackendKind, NativeGLContextMethods};
 
 const DUMMY_BUFFER_WIDTH: usize = 16;
 const DUMMY_BUFFER_HEIGHT: usize = 16;
 
+/// How to set up an OSMesa context and the buffer it renders into.
+#[derive(Clone, Copy, Debug)]
+pub struct OSMesaConfig {
+    /// One of `OSMESA_RGBA`, `OSMESA_BGRA`, `OSMESA_ARGB`, `OSMESA_RGB`,
+    /// `OSMESA_BGR` or `OSMESA_RGB_565`.
+    pub format: c_uint,
+    pub depth_bits: c_int,
+    pub stencil_bits: c_int,
+    pub accum_bits: c_int,
+    /// The size of the buffer OSMesa renders into.
+    pub size: Size2D<i32>,
+}
+
+impl Default for OSMesaConfig {
+    fn default() -> OSMesaConfig {
+        OSMesaConfig {
+            format: osmesa_sys::OSMESA_RGBA,
+            depth_bits: 0,
+            stencil_bits: 0,
+            accum_bits: 0,
+            size: Size2D::new(DUMMY_BUFFER_WIDTH as i32, DUMMY_BUFFER_HEIGHT as i32),
+        }
+    }
+}
+
+impl OSMesaConfig {
+    // The bytes per pixel and GL type of the buffer for our format.
+    fn buffer_layout(&self) -> Result<(usize, gl::GLenum), &'static str> {
+        match self.format {
+            osmesa_sys::OSMESA_RGBA |
+            osmesa_sys::OSMESA_BGRA |
+            osmesa_sys::OSMESA_ARGB => Ok((4, gl::UNSIGNED_BYTE)),
+            osmesa_sys::OSMESA_RGB |
+            osmesa_sys::OSMESA_BGR => Ok((3, gl::UNSIGNED_BYTE)),
+            osmesa_sys::OSMESA_RGB_565 => Ok((2, gl::UNSIGNED_SHORT_5_6_5)),
+            _ => Err("Unsupported OSMesa format"),
+        }
+    }
+}
+
 pub struct OSMesaContext {
     buffer: Vec<u8>,
+    buffer_type: gl::GLenum,
+    config: OSMesaConfig,
     context: osmesa_sys::OSMesaContext,
 }
 
-pub struct OSMesaContextHandle(osmesa_sys::OSMesaContext);
+/// A handle to an OSMesa context, which may also come from outside of this
+/// crate.
+pub struct OSMesaContextHandle(pub osmesa_sys::OSMesaContext);
 
 unsafe impl Send for OSMesaContextHandle {}
 
@@ -25,6 +70,19 @@ impl OSMesaContext {
                api_type: &gl::GlType,
                api_version: GLVersion)
         -> Result<Self, &'static str> {
+        Self::new_with_config(share_with, api_type, api_version, &OSMesaConfig::default())
+    }
+
+    pub fn new_with_config(share_with: Option<osmesa_sys::OSMesaContext>,
+                           api_type: &gl::GlType,
+                           api_version: GLVersion,
+                           config: &OSMesaConfig)
+        -> Result<Self, &'static str> {
+        let (bytes_per_pixel, buffer_type) = config.buffer_layout()?;
+        if config.size.width <= 0 || config.size.height <= 0 {
+            return Err("Invalid OSMesa buffer size");
+        }
+
         let shared = match share_with {
             Some(ctx) => ctx,
             _ => ptr::null_mut(),
@@ -46,7 +104,10 @@ impl OSMesaContext {
         };
 
         let attributes = [
-            osmesa_sys::OSMESA_FORMAT, osmesa_sys::OSMESA_RGBA as c_int,
+            osmesa_sys::OSMESA_FORMAT, config.format as c_int,
+            osmesa_sys::OSMESA_DEPTH_BITS, config.depth_bits,
+            osmesa_sys::OSMESA_STENCIL_BITS, config.stencil_bits,
+            osmesa_sys::OSMESA_ACCUM_BITS, config.accum_bits,
             osmesa_sys::OSMESA_CONTEXT_MAJOR_VERSION, major as c_int,
             osmesa_sys::OSMESA_CONTEXT_MINOR_VERSION, minor as c_int,
             0
@@ -60,17 +121,39 @@ impl OSMesaContext {
             return Err("OSMesaCreateContext");
         }
 
-        let buffer = vec![0u8; DUMMY_BUFFER_WIDTH * DUMMY_BUFFER_HEIGHT * 4];
+        let buffer_len = config.size.width as usize * config.size.height as usize * bytes_per_pixel;
         Ok(OSMesaContext {
-            buffer: buffer,
+            buffer: vec![0u8; buffer_len],
+            buffer_type: buffer_type,
+            config: *config,
             context: context,
         })
     }
+
+    pub fn config(&self) -> &OSMesaConfig {
+        &self.config
+    }
+
+    /// The pixels OSMesa renders into, bottom row first, laid out as
+    /// described by `config()`.
+    pub fn buffer(&self) -> &[u8] {
+        &self.buffer
+    }
+
+    /// A raw pointer to `buffer()`, for consumers that hand it to other
+    /// software rendering code. It's valid as long as this context lives.
+    pub fn buffer_ptr(&self) -> *const u8 {
+        self.buffer.as_ptr()
+    }
 }
 
 impl NativeGLContextMethods for OSMesaContext {
     type Handle = OSMesaContextHandle;
 
+    fn backend() -> BackendKind {
+        BackendKind::OSMesa
+    }
+
     fn get_proc_address(addr: &str) -> *const () {
         let addr = CString::new(addr.as_bytes()).unwrap();
         let addr = addr.as_ptr();
@@ -115,9 +198,9 @@ impl NativeGLContextMethods for OSMesaContext {
             if !self.is_current() &&
                osmesa_sys::OSMesaMakeCurrent(self.context,
                                              self.buffer.as_ptr() as *const _ as *mut _,
-                                             gl::UNSIGNED_BYTE,
-                                             DUMMY_BUFFER_WIDTH as i32,
-                                             DUMMY_BUFFER_HEIGHT as i32) == 0 {
+                                             self.buffer_type,
+                                             self.config.size.width,
+                                             self.config.size.height) == 0 {
                Err("OSMesaMakeCurrent")
            } else {
                Ok(())
@@ -139,7 +222,6 @@ impl NativeGLContextMethods for OSMesaContext {
         Ok(())
     }
 
-    fn is_osmesa(&self) -> bool { true }
 }
 
 impl Drop for OSMesaContext {
@@ -1,6 +1,9 @@
-use platform::NativeGLContextMethods;
+use platform::{BackendKind, NativeGLContextMethods};
 use gleam::gl;
 use gl_context::GLContextDispatcher;
+use ContextPriority;
+use GLContextAttributes;
+use GLProfile;
 use GLVersion;
 use std::ffi::CString;
 use std::os::raw::c_void;
@@ -74,13 +77,35 @@ impl Drop for NativeGLContext {
 unsafe impl Send for NativeGLContext {}
 unsafe impl Sync for NativeGLContext {}
 
-pub struct NativeGLContextHandle(HGLRC, HDC);
+impl NativeGLContext {
+    /// Wraps a render context created elsewhere along with the device
+    /// context to make it current on. Neither is released on drop.
+    ///
+    /// # Safety
+    ///
+    /// Both handles must be valid, and stay valid as long as the wrapper.
+    pub unsafe fn from_raw_parts(render_ctx: HGLRC, device_ctx: HDC) -> NativeGLContext {
+        NativeGLContext {
+            render_ctx: render_ctx,
+            device_ctx: device_ctx,
+            weak: true,
+        }
+    }
+}
+
+/// A WGL render context and its device context. The fields are public so
+/// that embedders can share lists with an `HGLRC` they created themselves.
+pub struct NativeGLContextHandle(pub HGLRC, pub HDC);
 unsafe impl Send for NativeGLContextHandle {}
 unsafe impl Sync for NativeGLContextHandle {}
 
 impl NativeGLContextMethods for NativeGLContext {
     type Handle = NativeGLContextHandle;
 
+    fn backend() -> BackendKind {
+        BackendKind::Wgl
+    }
+
     fn get_proc_address(addr: &str) -> *const () {
         let addr = CString::new(addr.as_bytes()).unwrap();
         let addr = addr.as_ptr();
@@ -123,6 +148,16 @@ impl NativeGLContextMethods for NativeGLContext {
                                      api_version: GLVersion,
                                      dispatcher: Option<Box<GLContextDispatcher>>)
         -> Result<NativeGLContext, &'static str> {
+        Self::create_shared_with_attributes(with, api_type, api_version,
+                                            &GLContextAttributes::any(), dispatcher)
+    }
+
+    fn create_shared_with_attributes(with: Option<&Self::Handle>,
+                                     api_type: &gl::GlType,
+                                     api_version: GLVersion,
+                                     context_attributes: &GLContextAttributes,
+                                     dispatcher: Option<Box<GLContextDispatcher>>)
+        -> Result<NativeGLContext, &'static str> {
         let (render_ctx, device_ctx) = match with {
             Some(ref handle) => (handle.0, handle.1),
             None => (ptr::null_mut(), ptr::null_mut())
@@ -155,6 +190,16 @@ impl NativeGLContextMethods for NativeGLContext {
             gl::GlType::Gles => true,
             _ => false,
         };
+        attributes.robust_access = context_attributes.robust_access;
+        attributes.debug = context_attributes.debug;
+        attributes.no_error = context_attributes.no_error;
+        attributes.core_profile = context_attributes.profile == GLProfile::Core;
+        if context_attributes.robust_resource_initialization {
+            warn!("WGL can't provide robust resource initialization, ignoring");
+        }
+        if context_attributes.priority != ContextPriority::Medium {
+            warn!("WGL can't provide context priorities, ignoring");
+        }
 
         match api_version {
             GLVersion::Major(major) => {
@@ -232,6 +277,16 @@ impl NativeGLContextMethods for NativeGLContext {
     }
 
     fn make_current(&self) -> Result<(), &'static str> {
+        // Like on the other backends, skip the call (which flushes on some
+        // drivers) if there's nothing to do.
+        if self.is_current() {
+            Ok(())
+        } else {
+            self.make_current_force()
+        }
+    }
+
+    fn make_current_force(&self) -> Result<(), &'static str> {
         unsafe {
             if wgl::MakeCurrent(self.device_ctx as *const _, self.render_ctx as *const _) != 0 {
                 Ok(())
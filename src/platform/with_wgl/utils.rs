@@ -26,6 +26,8 @@ use winapi::um::winuser::{GetWindowPlacement};
 use super::wgl;
 use super::wgl_ext;
 
+// From WGL_ARB_create_context_no_error, which our bindings don't include.
+const CONTEXT_OPENGL_NO_ERROR_ARB: c_int = 0x31B3;
 
 // #Attributions
 // This WGL implementation has been inspired by the code originating in Glutin.
@@ -103,8 +105,9 @@ unsafe fn create_full_context(settings: &WGLAttributes,
                               hdc: HDC,
                               share: HGLRC)
                               -> Result<(HGLRC, HDC), String> {
-    let mut extensions = extensions.split(' ');
-    if extensions.find(|&i| i == "WGL_ARB_create_context").is_none() {
+    // NB: Every lookup needs a fresh iterator, `find` consumes what it skips.
+    let has_extension = |name: &str| extensions.split(' ').any(|i| i == name);
+    if !has_extension("WGL_ARB_create_context") {
         let ctx = create_basic_context(hdc, share);
         if let Ok(ctx) = ctx {
             if wgl::MakeCurrent(ctx.1 as *const _, ctx.0 as *const _) == 0  {
@@ -116,7 +119,7 @@ unsafe fn create_full_context(settings: &WGLAttributes,
 
     let mut attributes = Vec::new();
     if settings.opengl_es {
-        if extensions.find(|&i| i == "WGL_EXT_create_context_es2_profile").is_some() {
+        if has_extension("WGL_EXT_create_context_es2_profile") {
             attributes.push(wgl_ext::CONTEXT_PROFILE_MASK_ARB as c_int);
             attributes.push(wgl_ext::CONTEXT_ES2_PROFILE_BIT_EXT as c_int);
         } else {
@@ -131,12 +134,51 @@ unsafe fn create_full_context(settings: &WGLAttributes,
         attributes.push(settings.minor_version as c_int);
     }
 
-    attributes.push(wgl_ext::CONTEXT_FLAGS_ARB as c_int);
-    attributes.push((if settings.debug {
-        wgl_ext::CONTEXT_FLAGS_ARB
+    // Profiles only exist since OpenGL 3.2.
+    let has_profiles = settings.major_version > 3 ||
+                       (settings.major_version == 3 && settings.minor_version >= 2);
+    if !settings.opengl_es && has_profiles {
+        if has_extension("WGL_ARB_create_context_profile") {
+            let profile = if settings.core_profile {
+                wgl_ext::CONTEXT_CORE_PROFILE_BIT_ARB
+            } else {
+                wgl_ext::CONTEXT_COMPATIBILITY_PROFILE_BIT_ARB
+            };
+            attributes.push(wgl_ext::CONTEXT_PROFILE_MASK_ARB as c_int);
+            attributes.push(profile as c_int);
+        } else if !settings.core_profile {
+            // Without the extension we'd get a core profile.
+            return Err("Compatibility profile not supported".to_owned());
+        }
+    }
+
+    let mut flags = if settings.debug {
+        wgl_ext::CONTEXT_DEBUG_BIT_ARB
     } else {
         0
-    }) as c_int);
+    };
+
+    if settings.robust_access {
+        if has_extension("WGL_ARB_create_context_robustness") {
+            flags |= wgl_ext::CONTEXT_ROBUST_ACCESS_BIT_ARB;
+            attributes.push(wgl_ext::CONTEXT_RESET_NOTIFICATION_STRATEGY_ARB as c_int);
+            attributes.push(wgl_ext::LOSE_CONTEXT_ON_RESET_ARB as c_int);
+        } else {
+            warn!("WGL_ARB_create_context_robustness not supported, ignoring robust_access");
+        }
+    }
+
+    if settings.no_error {
+        if has_extension("WGL_ARB_create_context_no_error") {
+            attributes.push(CONTEXT_OPENGL_NO_ERROR_ARB);
+            attributes.push(1);
+        } else {
+            warn!("WGL_ARB_create_context_no_error not supported, ignoring no_error");
+        }
+    }
+
+    attributes.push(wgl_ext::CONTEXT_FLAGS_ARB as c_int);
+    attributes.push(flags as c_int);
 
     attributes.push(0);
 
@@ -154,7 +196,7 @@ unsafe fn create_full_context(settings: &WGLAttributes,
         return Err("wglMakeCurrent failed creating full context".to_owned());
     }
     // Disable or enable vsync
-    if extensions.find(|&i| i == "WGL_EXT_swap_control").is_some() {
+    if has_extension("WGL_EXT_swap_control") {
         if extra.SwapIntervalEXT(if settings.vsync { 1 } else { 0 }) == 0 {
             return Err("wglSwapIntervalEXT failed".to_owned());
         }
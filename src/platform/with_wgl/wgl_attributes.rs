@@ -4,8 +4,11 @@ pub struct WGLAttributes {
     pub opengl_es: bool, // enable or disable OpenGL ES contest
     pub major_version: u32, // OpenGL major version. Set 0 to select the latest
     pub minor_version: u32, // OpenGL minor version.
+    pub core_profile: bool, // Core or compatibility profile, for OpenGL >= 3.2. Core by default.
     pub debug: bool, // Debug mode improves error information. Disabled by default.
     pub vsync: bool, // Enable or disable vsync for swap_buffers. Disabled by default.
+    pub robust_access: bool, // Request a context with robust buffer access. Disabled by default.
+    pub no_error: bool, // Skip error checking, see WGL_ARB_create_context_no_error. Disabled by default.
     pub pixel_format: WGLPixelFormat, // Pixel format requirements
 }
 
@@ -16,8 +19,11 @@ impl Default for WGLAttributes {
             opengl_es: false,
             major_version: 2,
             minor_version: 1,
+            core_profile: true,
             debug: false,
             vsync: false,
+            robust_access: false,
+            no_error: false,
             pixel_format: WGLPixelFormat::default(),
         }
     }
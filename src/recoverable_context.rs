@@ -0,0 +1,112 @@
+use euclid::Size2D;
+use gleam::gl;
+use std::mem;
+
+use ColorAttachmentType;
+use ContextLossStatus;
+use GLContext;
+use GLContextAttributes;
+use GLVersion;
+use NativeGLContextMethods;
+
+/// A `GLContext` that can be recreated after a GPU reset.
+///
+/// Call `recover_if_lost()` regularly (once per frame, say). When the driver
+/// reports that the context was lost, it's replaced with a fresh one created
+/// from the same parameters, and `on_recreated` is called with it so that
+/// resources can be uploaded again. Everything created in the old context,
+/// including the draw buffer contents, is gone by then.
+///
+/// Contexts are always created with `robust_access`, otherwise resets
+/// wouldn't be reported. Sharing isn't supported, since whatever we
+/// shared with is likely lost as well.
+pub struct RecoverableContext<Native> {
+    context: GLContext<Native>,
+    attributes: GLContextAttributes,
+    color_attachment_type: ColorAttachmentType,
+    api_type: gl::GlType,
+    api_version: GLVersion,
+    on_recreated: Box<dyn FnMut(&GLContext<Native>)>,
+}
+
+impl<Native> RecoverableContext<Native>
+    where Native: NativeGLContextMethods,
+{
+    pub fn new<F>(size: Size2D<i32>,
+                  attributes: GLContextAttributes,
+                  color_attachment_type: ColorAttachmentType,
+                  api_type: gl::GlType,
+                  api_version: GLVersion,
+                  on_recreated: F)
+        -> Result<Self, &'static str>
+        where F: FnMut(&GLContext<Native>) + 'static
+    {
+        let attributes = GLContextAttributes {
+            robust_access: true,
+            .. attributes
+        };
+        let context = GLContext::new(size,
+                                     attributes,
+                                     color_attachment_type,
+                                     api_type,
+                                     api_version,
+                                     None)?;
+        Ok(RecoverableContext {
+            context: context,
+            attributes: attributes,
+            color_attachment_type: color_attachment_type,
+            api_type: api_type,
+            api_version: api_version,
+            on_recreated: Box::new(on_recreated),
+        })
+    }
+
+    #[inline(always)]
+    pub fn context(&self) -> &GLContext<Native> {
+        &self.context
+    }
+
+    #[inline(always)]
+    pub fn context_mut(&mut self) -> &mut GLContext<Native> {
+        &mut self.context
+    }
+
+    /// Checks whether the context was lost, and if so recreates it with a
+    /// draw buffer of the same size, and calls `on_recreated`. Returns what
+    /// the driver reported, so callers can tell whether they caused the
+    /// reset. The context must be current, and it's current again when
+    /// this returns successfully.
+    pub fn recover_if_lost(&mut self) -> Result<ContextLossStatus, &'static str> {
+        let status = self.context.check_context_lost()?;
+        if status == ContextLossStatus::NotLost {
+            return Ok(status);
+        }
+
+        warn!("GL context lost ({:?}), recreating it", status);
+        let size = match self.context.draw_buffer_size() {
+            Some(size) => size,
+            None => return Err("No DrawBuffer found"),
+        };
+
+        let context = GLContext::new(size,
+                                     self.attributes,
+                                     self.color_attachment_type,
+                                     self.api_type,
+                                     self.api_version,
+                                     None)?;
+
+        // The draw buffer deletes its objects through the current context,
+        // so drop the old one with it bound, or at least without the new one
+        // bound. There's nothing left to delete in a lost context anyway.
+        let lost = mem::replace(&mut self.context, context);
+        if lost.make_current().is_err() {
+            self.context.unbind()?;
+        }
+        drop(lost);
+
+        self.context.make_current()?;
+        (self.on_recreated)(&self.context);
+
+        Ok(status)
+    }
+}
@@ -6,14 +6,39 @@ use GLContext;
 use platform::with_egl::NativeGLContext;
 #[cfg(feature="test_osmesa")]
 use platform::with_osmesa::OSMesaContext as NativeGLContext;
+#[cfg(all(target_os = "linux", feature = "test_egl_in_linux"))]
+use platform::with_egl::NativeGLContextHandle;
 #[cfg(not(any(feature = "test_egl_in_linux", feature = "test_osmesa")))]
 use NativeGLContext;
 use NativeGLContextMethods;
+use BackendKind;
 use GLContextAttributes;
+use GLProfile;
 use GLVersion;
 use ColorAttachmentType;
+use ContextLossStatus;
+use ContextPriority;
+use RecoverableContext;
+use OUT_OF_MEMORY;
+use std::cell::{Cell, RefCell};
+use std::rc::Rc;
+use std::panic;
 use std::thread;
 use std::sync::mpsc;
+use std::time::Duration;
+
+// Creates a context with a draw buffer, which is what most tests need.
+fn new_context(size: Size2D<i32>,
+               attributes: GLContextAttributes,
+               color_attachment_type: ColorAttachmentType,
+               api_version: GLVersion) -> GLContext<NativeGLContext> {
+    GLContext::<NativeGLContext>::new(size,
+                                      attributes,
+                                      color_attachment_type,
+                                      gl::GlType::default(),
+                                      api_version,
+                                      None).unwrap()
+}
 
 fn test_gl_context<T: NativeGLContextMethods>(context: &GLContext<T>) {
     context.make_current().unwrap();
@@ -68,6 +93,83 @@ fn test_unbinding_gl3() {
     test_unbinding(GLVersion::Major(3));
 }
 
+#[test]
+#[cfg(not(feature = "test_osmesa"))]
+fn test_with_current() {
+    let size = Size2D::new(256, 256);
+    let primary = new_context(size,
+                              GLContextAttributes::default(),
+                              ColorAttachmentType::Renderbuffer,
+                              GLVersion::Major(3));
+    let secondary = new_context(size,
+                                GLContextAttributes::default(),
+                                ColorAttachmentType::Renderbuffer,
+                                GLVersion::Major(3));
+
+    primary.make_current().unwrap();
+    let was_current = secondary.with_current(|ctx| ctx.is_current()).unwrap();
+    assert!(was_current);
+    assert!(primary.is_current());
+
+    primary.unbind().unwrap();
+    secondary.with_current(|ctx| test_gl_context(ctx)).unwrap();
+    assert!(NativeGLContext::current_handle().is_none());
+}
+
+#[test]
+#[cfg(not(feature = "test_osmesa"))]
+fn test_make_current_scoped() {
+    let size = Size2D::new(256, 256);
+    let primary = new_context(size,
+                              GLContextAttributes::default(),
+                              ColorAttachmentType::Renderbuffer,
+                              GLVersion::Major(3));
+    let secondary = new_context(size,
+                                GLContextAttributes::default(),
+                                ColorAttachmentType::Renderbuffer,
+                                GLVersion::Major(3));
+
+    primary.make_current().unwrap();
+    {
+        let _guard = secondary.make_current_scoped().unwrap();
+        assert!(secondary.is_current());
+        test_gl_context(&secondary);
+    }
+    assert!(primary.is_current());
+
+    // Nothing was current, so nothing is afterwards either.
+    primary.unbind().unwrap();
+    secondary.make_current_scoped().unwrap().restore().unwrap();
+    assert!(NativeGLContext::current_handle().is_none());
+
+    // Also restored when unwinding.
+    primary.make_current().unwrap();
+    let result = panic::catch_unwind(panic::AssertUnwindSafe(|| {
+        let _guard = secondary.make_current_scoped().unwrap();
+        panic!("Plugin crashed");
+    }));
+    assert!(result.is_err());
+    assert!(primary.is_current());
+}
+
+#[test]
+fn test_make_current_force() {
+    let context = new_context(Size2D::new(256, 256),
+                              GLContextAttributes::default(),
+                              ColorAttachmentType::Renderbuffer,
+                              GLVersion::Major(3));
+
+    // Works whether or not the context is current already.
+    context.make_current_force().unwrap();
+    assert!(context.is_current());
+    test_gl_context(&context);
+
+    context.unbind().unwrap();
+    context.make_current_force().unwrap();
+    assert!(context.is_current());
+    test_gl_context(&context);
+}
+
 fn test_renderbuffer_color_attachment(api_version: GLVersion) {
     test_gl_context(&GLContext::<NativeGLContext>::new(Size2D::new(256, 256),
                                                        GLContextAttributes::default(),
@@ -101,6 +203,19 @@ fn test_texture_color_attachment(api_version: GLVersion) {
     let texture_id = context.borrow_draw_buffer().unwrap().get_bound_texture_id().unwrap();
     assert!(texture_id != 0);
 
+    // The same information is available without making the context current.
+    context.unbind().unwrap();
+    let (id, texture_size, format) = context.draw_buffer_texture().unwrap();
+    assert_eq!(id, texture_id);
+    assert_eq!(texture_size, size);
+    let formats = context.borrow_formats();
+    if formats.texture_storage {
+        assert_eq!(format, formats.color_renderbuffer);
+    } else {
+        assert_eq!(format, formats.texture_internal);
+    }
+    context.make_current().unwrap();
+
     assert!(context.gl().get_error() == gl::NO_ERROR);
 
     // Actually we just check that writing to the framebuffer works, and that there's a texture
@@ -184,6 +299,106 @@ fn test_sharing_gl3() {
     test_sharing(GLVersion::Major(3));
 }
 
+// Check that we can share with a context someone else created with plain
+// EGL, given to us as raw native handles.
+#[cfg(all(target_os = "linux", feature = "test_egl_in_linux"))]
+fn test_sharing_with_foreign_context(api_version: GLVersion) {
+    use egl;
+    use egl::types::{EGLConfig, EGLint, EGLNativeDisplayType};
+    use std::ptr;
+
+    let display = unsafe { egl::GetDisplay(egl::DEFAULT_DISPLAY as EGLNativeDisplayType) };
+    assert!(unsafe { egl::Initialize(display, ptr::null_mut(), ptr::null_mut()) } != 0);
+
+    let config_attributes = [
+        egl::SURFACE_TYPE as EGLint, egl::PBUFFER_BIT as EGLint,
+        egl::RENDERABLE_TYPE as EGLint, egl::OPENGL_ES2_BIT as EGLint,
+        egl::RED_SIZE as EGLint, 8,
+        egl::GREEN_SIZE as EGLint, 8,
+        egl::BLUE_SIZE as EGLint, 8,
+        egl::ALPHA_SIZE as EGLint, 8,
+        egl::NONE as EGLint,
+    ];
+    let mut config: EGLConfig = ptr::null();
+    let mut found = 0;
+    assert!(unsafe {
+        egl::ChooseConfig(display, config_attributes.as_ptr(), &mut config, 1, &mut found)
+    } != 0);
+    assert_eq!(found, 1);
+
+    let context_attributes = [
+        egl::CONTEXT_CLIENT_VERSION as EGLint, api_version.major_version() as EGLint,
+        egl::NONE as EGLint,
+    ];
+    let surface_attributes = [
+        egl::WIDTH as EGLint, 16,
+        egl::HEIGHT as EGLint, 16,
+        egl::NONE as EGLint,
+    ];
+    let foreign_context = unsafe {
+        egl::CreateContext(display, config, egl::NO_CONTEXT as *const _, context_attributes.as_ptr())
+    };
+    assert!(foreign_context != egl::NO_CONTEXT as *const _);
+    let foreign_surface = unsafe {
+        egl::CreatePbufferSurface(display, config, surface_attributes.as_ptr())
+    };
+    assert!(foreign_surface != egl::NO_SURFACE as *const _);
+
+    // Upload a red texture from the foreign context.
+    assert!(unsafe {
+        egl::MakeCurrent(display, foreign_surface, foreign_surface, foreign_context)
+    } != 0);
+    let foreign_gl = unsafe {
+        gl::GlesFns::load_with(|s| GLContext::<NativeGLContext>::get_proc_address(s) as *const _)
+    };
+    let texture = foreign_gl.gen_textures(1)[0];
+    foreign_gl.bind_texture(gl::TEXTURE_2D, texture);
+    foreign_gl.tex_image_2d(gl::TEXTURE_2D, 0, gl::RGBA as i32, 1, 1, 0,
+                            gl::RGBA, gl::UNSIGNED_BYTE, Some(&[255, 0, 0, 255]));
+    foreign_gl.bind_texture(gl::TEXTURE_2D, 0);
+    foreign_gl.finish();
+
+    let foreign = NativeGLContextHandle(foreign_context, display);
+    let context = GLContext::<NativeGLContext>::new(Size2D::new(256, 256),
+                                                    GLContextAttributes::default(),
+                                                    ColorAttachmentType::Texture,
+                                                    gl::GlType::default(),
+                                                    api_version,
+                                                    Some(&foreign)).unwrap();
+    context.make_current().unwrap();
+    assert!(context.gl().is_texture(texture) != 0);
+
+    // And read it back through our context.
+    let framebuffer = context.gl().gen_framebuffers(1)[0];
+    context.gl().bind_framebuffer(gl::FRAMEBUFFER, framebuffer);
+    context.gl().framebuffer_texture_2d(gl::FRAMEBUFFER, gl::COLOR_ATTACHMENT0,
+                                        gl::TEXTURE_2D, texture, 0);
+    let pixels = context.gl().read_pixels(0, 0, 1, 1, gl::RGBA, gl::UNSIGNED_BYTE);
+    test_pixels(&pixels);
+    context.gl().bind_framebuffer(gl::FRAMEBUFFER, context.get_framebuffer());
+    context.gl().delete_framebuffers(&[framebuffer]);
+    drop(context);
+
+    unsafe {
+        egl::MakeCurrent(display, egl::NO_SURFACE as *const _, egl::NO_SURFACE as *const _,
+                         egl::NO_CONTEXT as *const _);
+        egl::DestroySurface(display, foreign_surface);
+        egl::DestroyContext(display, foreign_context);
+    }
+}
+
+#[test]
+#[cfg(all(target_os = "linux", feature = "test_egl_in_linux"))]
+fn test_sharing_with_foreign_context_gl2() {
+    test_sharing_with_foreign_context(GLVersion::Major(2));
+}
+
+#[test]
+#[cfg(all(target_os = "linux", feature = "test_egl_in_linux"))]
+fn test_sharing_with_foreign_context_gl3() {
+    test_sharing_with_foreign_context(GLVersion::Major(3));
+}
+
 fn test_multithread_render(api_version: GLVersion) {
     let size = Size2D::new(256, 256);
     let primary = GLContext::<NativeGLContext>::new(size,
@@ -551,3 +766,773 @@ fn test_stencil_no_depth_gl2() {
 fn test_stencil_no_depth_gl3() {
     test_stencil_no_depth(GLVersion::Major(3));
 }
+
+#[test]
+#[cfg(feature = "clear_on_create")]
+fn test_cleared_on_creation() {
+    let size = Size2D::new(256, 256);
+    let mut context = new_context(size,
+                                  GLContextAttributes::default(),
+                                  ColorAttachmentType::Texture,
+                                  GLVersion::Major(3));
+
+    let vec = context.gl().read_pixels(0, 0, size.width, size.height, gl::RGBA, gl::UNSIGNED_BYTE);
+    test_pixels_eq(&vec, &[0, 0, 0, 0]);
+
+    // Paint it, and check that resizing gives us a clean buffer again, even
+    // with writes masked, and that the embedder's state is left alone.
+    test_gl_context(&context);
+    context.gl().clear_color(0.0, 1.0, 0.0, 1.0);
+    context.gl().color_mask(true, false, true, false);
+    context.gl().depth_mask(false);
+
+    let size = Size2D::new(128, 128);
+    context.resize(size).unwrap();
+    let vec = context.gl().read_pixels(0, 0, size.width, size.height, gl::RGBA, gl::UNSIGNED_BYTE);
+    test_pixels_eq(&vec, &[0, 0, 0, 0]);
+
+    let mut clear_color = [0.0; 4];
+    let mut color_mask = [0; 4];
+    let mut depth_mask = [0];
+    unsafe {
+        context.gl().get_float_v(gl::COLOR_CLEAR_VALUE, &mut clear_color);
+        context.gl().get_boolean_v(gl::COLOR_WRITEMASK, &mut color_mask);
+        context.gl().get_boolean_v(gl::DEPTH_WRITEMASK, &mut depth_mask);
+    }
+    assert_eq!(clear_color, [0.0, 1.0, 0.0, 1.0]);
+    assert_eq!(color_mask, [gl::TRUE, gl::FALSE, gl::TRUE, gl::FALSE]);
+    assert_eq!(depth_mask, [gl::FALSE]);
+}
+
+#[test]
+fn test_pending_gl_error() {
+    let size = Size2D::new(256, 256);
+    let mut context = new_context(size,
+                                  GLContextAttributes::default(),
+                                  ColorAttachmentType::Texture,
+                                  GLVersion::Major(3));
+
+    // Not a texture target.
+    context.gl().bind_texture(gl::RGBA, 0);
+    context.resize(Size2D::new(128, 128)).unwrap();
+
+    // Unless asked to check errors, we don't clear the embedder's. When
+    // checking them, this one isn't ours to report.
+    if cfg!(feature = "check_gl_errors") {
+        assert_eq!(context.gl().get_error(), gl::NO_ERROR);
+    } else {
+        assert_eq!(context.gl().get_error(), gl::INVALID_ENUM);
+    }
+    test_gl_context(&context);
+}
+
+fn test_immutable_storage(api_version: GLVersion) {
+    let size = Size2D::new(256, 256);
+    let context = new_context(size,
+                              GLContextAttributes::default(),
+                              ColorAttachmentType::Texture,
+                              api_version);
+    let draw_buffer = context.borrow_draw_buffer().unwrap();
+    assert_eq!(draw_buffer.has_immutable_storage(), context.borrow_formats().texture_storage);
+    test_gl_context(&context);
+
+    let context = new_context(size,
+                              GLContextAttributes::default(),
+                              ColorAttachmentType::Renderbuffer,
+                              api_version);
+    assert!(!context.borrow_draw_buffer().unwrap().has_immutable_storage());
+}
+
+#[test]
+fn test_immutable_storage_gl2() {
+    test_immutable_storage(GLVersion::Major(2));
+}
+
+#[test]
+fn test_immutable_storage_gl3() {
+    test_immutable_storage(GLVersion::Major(3));
+}
+
+#[test]
+fn test_memory_usage() {
+    let size = Size2D::new(256, 256);
+    let attributes = GLContextAttributes {
+        depth: false,
+        .. Default::default()
+    };
+    let context = new_context(size, attributes, ColorAttachmentType::Texture, GLVersion::Major(3));
+    let color_only = context.borrow_draw_buffer().unwrap().memory_usage();
+    assert_eq!(color_only, 256 * 256 * 4);
+
+    let context = new_context(size,
+                              GLContextAttributes::default(),
+                              ColorAttachmentType::Renderbuffer,
+                              GLVersion::Major(3));
+    assert!(context.borrow_draw_buffer().unwrap().memory_usage() > color_only);
+}
+
+fn test_antialias(api_version: GLVersion) {
+    let size = Size2D::new(256, 256);
+    let attributes = GLContextAttributes {
+        antialias: true,
+        .. Default::default()
+    };
+    let context = new_context(size, attributes, ColorAttachmentType::Texture, api_version);
+
+    // Antialiasing is a request, so there's nothing else to test if the
+    // context can't do it. It's core in GL 3 and GLES 3 though, which we
+    // often get even when asking for 2.
+    if context.api_version().unwrap().major_version() >= 3 {
+        assert!(context.needs_resolve());
+    }
+    if !context.needs_resolve() {
+        return;
+    }
+    assert!(context.borrow_draw_buffer().unwrap().samples() > 0);
+
+    context.gl().clear_color(1.0, 0.0, 0.0, 1.0);
+    context.gl().clear(gl::COLOR_BUFFER_BIT);
+    context.resolve_draw_buffer();
+
+    // Resolving mustn't change the bound framebuffer.
+    let mut bound = [0];
+    unsafe {
+        context.gl().get_integer_v(gl::FRAMEBUFFER_BINDING, &mut bound);
+    }
+    assert_eq!(bound[0] as u32, context.get_framebuffer());
+
+    // Read the resolved texture back through another framebuffer.
+    let texture_id = context.borrow_draw_buffer().unwrap().get_bound_texture_id().unwrap();
+    let framebuffer = context.gl().gen_framebuffers(1)[0];
+    context.gl().bind_framebuffer(gl::FRAMEBUFFER, framebuffer);
+    context.gl().framebuffer_texture_2d(gl::FRAMEBUFFER,
+                                        gl::COLOR_ATTACHMENT0,
+                                        gl::TEXTURE_2D,
+                                        texture_id, 0);
+
+    let vec = context.gl().read_pixels(0, 0, size.width, size.height, gl::RGBA, gl::UNSIGNED_BYTE);
+    test_pixels(&vec);
+
+    context.gl().bind_framebuffer(gl::FRAMEBUFFER, context.get_framebuffer());
+    context.gl().delete_framebuffers(&[framebuffer]);
+    assert_eq!(context.gl().get_error(), gl::NO_ERROR);
+}
+
+#[test]
+fn test_antialias_gl2() {
+    test_antialias(GLVersion::Major(2));
+}
+
+#[test]
+fn test_antialias_gl3() {
+    test_antialias(GLVersion::Major(3));
+}
+
+#[test]
+fn test_swap_draw_buffer() {
+    let mut context = GLContext::<NativeGLContext>::new(Size2D::new(256, 256),
+                                                        GLContextAttributes::default(),
+                                                        ColorAttachmentType::Texture,
+                                                        gl::GlType::default(),
+                                                        GLVersion::Major(3),
+                                                        None).unwrap();
+    test_gl_context(&context);
+
+    let old_draw_buffer = context.swap_draw_buffer(Size2D::new(128, 64)).unwrap();
+    assert_eq!(old_draw_buffer.size(), Size2D::new(256, 256));
+    assert_eq!(context.draw_buffer_size(), Some(Size2D::new(128, 64)));
+    assert!(old_draw_buffer.get_framebuffer() != context.get_framebuffer());
+    assert!(old_draw_buffer.get_bound_texture_id() != context.draw_buffer_texture().map(|t| t.0));
+
+    // The new draw buffer starts out cleared...
+    let pixels = context.gl().read_pixels(0, 0, 128, 64, gl::RGBA, gl::UNSIGNED_BYTE);
+    test_pixels_eq(&pixels, &[0, 0, 0, 0]);
+
+    // ... while the old one keeps what we drew.
+    context.gl().bind_framebuffer(gl::FRAMEBUFFER, old_draw_buffer.get_framebuffer());
+    let pixels = context.gl().read_pixels(0, 0, 256, 256, gl::RGBA, gl::UNSIGNED_BYTE);
+    test_pixels(&pixels);
+    context.gl().bind_framebuffer(gl::FRAMEBUFFER, context.get_framebuffer());
+
+    drop(old_draw_buffer);
+    assert_eq!(context.gl().get_error(), gl::NO_ERROR);
+}
+
+#[test]
+fn test_failed_swap_draw_buffer() {
+    let attributes = GLContextAttributes {
+        antialias: true,
+        .. Default::default()
+    };
+    let mut context = GLContext::<NativeGLContext>::new(Size2D::new(256, 256),
+                                                        attributes,
+                                                        ColorAttachmentType::Renderbuffer,
+                                                        gl::GlType::default(),
+                                                        GLVersion::Major(3),
+                                                        None).unwrap();
+    if context.borrow_capabilities().max_samples == 0 {
+        return;
+    }
+
+    // Runs out of memory, see test_out_of_memory.
+    let framebuffer = context.get_framebuffer();
+    let max_size = context.borrow_limits().max_renderbuffer_size as i32;
+    assert_eq!(context.swap_draw_buffer(Size2D::new(max_size, max_size)).err(),
+               Some(OUT_OF_MEMORY));
+    assert_eq!(context.get_framebuffer(), framebuffer);
+
+    let mut binding = [0];
+    unsafe {
+        context.gl().get_integer_v(gl::FRAMEBUFFER_BINDING, &mut binding);
+    }
+    assert_eq!(binding[0] as gl::GLuint, framebuffer);
+}
+
+fn test_backend_introspection(api_version: GLVersion) {
+    let context = GLContext::<NativeGLContext>::new(Size2D::new(256, 256),
+                                                    GLContextAttributes::default(),
+                                                    ColorAttachmentType::Renderbuffer,
+                                                    gl::GlType::default(),
+                                                    api_version,
+                                                    None).unwrap();
+
+    let expected = if cfg!(feature = "test_egl_in_linux") {
+        BackendKind::Egl
+    } else if cfg!(feature = "test_osmesa") {
+        BackendKind::OSMesa
+    } else if cfg!(target_os = "linux") {
+        BackendKind::Glx
+    } else if cfg!(target_os = "macos") {
+        BackendKind::Cgl
+    } else {
+        BackendKind::Wgl
+    };
+    assert_eq!(NativeGLContext::backend(), expected);
+
+    let version = context.api_version().unwrap();
+    assert!(version.major_version() >= api_version.major_version());
+}
+
+#[test]
+fn test_backend_introspection_gl2() {
+    test_backend_introspection(GLVersion::Major(2));
+}
+
+#[test]
+fn test_backend_introspection_gl3() {
+    test_backend_introspection(GLVersion::Major(3));
+}
+
+#[test]
+fn test_read_draw_buffer_into() {
+    let size = Size2D::new(64, 32);
+    for &antialias in &[false, true] {
+        for color_attachment_type in vec![ColorAttachmentType::Texture, ColorAttachmentType::Renderbuffer] {
+            let attributes = GLContextAttributes {
+                antialias: antialias,
+                .. Default::default()
+            };
+            let context = new_context(size, attributes, color_attachment_type, GLVersion::Major(3));
+            context.gl().clear_color(1.0, 0.0, 0.0, 1.0);
+            context.gl().clear(gl::COLOR_BUFFER_BIT);
+
+            // The embedder's read framebuffer and pack state are left alone.
+            let read_framebuffer = context.gl().gen_framebuffers(1)[0];
+            let pack_buffer = context.gl().gen_buffers(1)[0];
+            context.gl().bind_framebuffer(gl::READ_FRAMEBUFFER, read_framebuffer);
+            context.gl().bind_buffer(gl::PIXEL_PACK_BUFFER, pack_buffer);
+            context.gl().pixel_store_i(gl::PACK_ALIGNMENT, 8);
+
+            let mut pixels = vec![0; 64 * 32 * 4];
+            context.read_draw_buffer_into(&mut pixels).unwrap();
+            test_pixels(&pixels);
+
+            let mut bindings = [[0], [0], [0], [0]];
+            unsafe {
+                context.gl().get_integer_v(gl::READ_FRAMEBUFFER_BINDING, &mut bindings[0]);
+                context.gl().get_integer_v(gl::DRAW_FRAMEBUFFER_BINDING, &mut bindings[1]);
+                context.gl().get_integer_v(gl::PIXEL_PACK_BUFFER_BINDING, &mut bindings[2]);
+                context.gl().get_integer_v(gl::PACK_ALIGNMENT, &mut bindings[3]);
+            }
+            let framebuffer = context.borrow_draw_buffer().unwrap().get_framebuffer();
+            assert_eq!(bindings, [[read_framebuffer as i32], [framebuffer as i32],
+                                  [pack_buffer as i32], [8]]);
+
+            assert!(context.read_draw_buffer_into(&mut pixels[4..]).is_err());
+        }
+    }
+}
+
+fn test_async_readback(api_version: GLVersion) {
+    let size = Size2D::new(64, 32);
+    let context = new_context(size,
+                              GLContextAttributes::default(),
+                              ColorAttachmentType::Texture,
+                              api_version);
+    context.gl().clear_color(1.0, 0.0, 0.0, 1.0);
+    context.gl().clear(gl::COLOR_BUFFER_BIT);
+
+    if context.api_version().unwrap().major_version() < 3 {
+        assert!(context.begin_read_draw_buffer().is_err());
+        return;
+    }
+
+    // The embedder's pack state is left alone.
+    let pack_buffer = context.gl().gen_buffers(1)[0];
+    context.gl().bind_buffer(gl::PIXEL_PACK_BUFFER, pack_buffer);
+    context.gl().pixel_store_i(gl::PACK_ALIGNMENT, 8);
+
+    let readback = context.begin_read_draw_buffer().unwrap();
+    assert_eq!(readback.size(), size);
+
+    // Drawing after queueing the readback doesn't affect it.
+    context.gl().clear_color(0.0, 1.0, 0.0, 1.0);
+    context.gl().clear(gl::COLOR_BUFFER_BIT);
+    context.gl().finish();
+
+    let pixels = readback.try_resolve().unwrap().expect("Readback should be done after glFinish");
+    assert_eq!(pixels.len(), 64 * 32 * 4);
+    test_pixels(&pixels);
+    assert_eq!(context.gl().get_error(), gl::NO_ERROR);
+
+    let mut binding = [0];
+    let mut alignment = [0];
+    unsafe {
+        context.gl().get_integer_v(gl::PIXEL_PACK_BUFFER_BINDING, &mut binding);
+        context.gl().get_integer_v(gl::PACK_ALIGNMENT, &mut alignment);
+    }
+    assert_eq!(binding[0] as gl::GLuint, pack_buffer);
+    assert_eq!(alignment[0], 8);
+}
+
+#[test]
+fn test_async_readback_gl2() {
+    test_async_readback(GLVersion::Major(2));
+}
+
+#[test]
+fn test_async_readback_gl3() {
+    test_async_readback(GLVersion::Major(3));
+}
+
+fn test_fence(api_version: GLVersion) {
+    let context = GLContext::<NativeGLContext>::new(Size2D::new(256, 256),
+                                                    GLContextAttributes::default(),
+                                                    ColorAttachmentType::Texture,
+                                                    gl::GlType::default(),
+                                                    api_version,
+                                                    None).unwrap();
+    if context.api_version().unwrap().major_version() < 3 {
+        assert!(context.create_fence().is_err());
+        return;
+    }
+
+    context.gl().clear_color(1.0, 0.0, 0.0, 1.0);
+    context.gl().clear(gl::COLOR_BUFFER_BIT);
+    let fence = context.create_fence().unwrap();
+    assert!(fence.wait(Duration::from_secs(5)).unwrap());
+    assert!(fence.is_signaled().unwrap());
+
+    // A context sharing with this one can wait on the same fence.
+    let shared = GLContext::<NativeGLContext>::new(Size2D::new(256, 256),
+                                                   GLContextAttributes::default(),
+                                                   ColorAttachmentType::Texture,
+                                                   gl::GlType::default(),
+                                                   api_version,
+                                                   Some(&context.handle())).unwrap();
+    fence.wait_on_gpu(shared.gl());
+    assert_eq!(shared.gl().get_error(), gl::NO_ERROR);
+
+    context.make_current().unwrap();
+    drop(fence);
+    assert_eq!(context.gl().get_error(), gl::NO_ERROR);
+}
+
+#[test]
+fn test_fence_gl2() {
+    test_fence(GLVersion::Major(2));
+}
+
+#[test]
+fn test_fence_gl3() {
+    test_fence(GLVersion::Major(3));
+}
+
+#[test]
+#[cfg(feature = "test_osmesa")]
+fn test_osmesa_config() {
+    use osmesa_sys;
+    use OSMesaConfig;
+
+    let config = OSMesaConfig {
+        format: osmesa_sys::OSMESA_RGB_565,
+        depth_bits: 24,
+        stencil_bits: 8,
+        accum_bits: 0,
+        size: Size2D::new(64, 32),
+    };
+    let native = NativeGLContext::new_with_config(None,
+                                                  &gl::GlType::Gl,
+                                                  GLVersion::Major(2),
+                                                  &config).unwrap();
+    assert_eq!(native.config().format, osmesa_sys::OSMESA_RGB_565);
+    assert_eq!(native.config().depth_bits, 24);
+    assert_eq!(native.config().stencil_bits, 8);
+    assert_eq!(native.config().size, Size2D::new(64, 32));
+    assert_eq!(native.buffer().len(), 64 * 32 * 2);
+    assert_eq!(native.buffer_ptr(), native.buffer().as_ptr());
+
+    // Rendering to the default framebuffer ends up in the buffer.
+    let context = GLContext::from_native(native, gl::GlType::Gl, GLVersion::Major(2)).unwrap();
+    context.gl().bind_framebuffer(gl::FRAMEBUFFER, 0);
+    context.gl().clear_color(1.0, 0.0, 0.0, 1.0);
+    context.gl().clear(gl::COLOR_BUFFER_BIT);
+    context.gl().finish();
+
+    // Full red in RGB565, in native endianness.
+    let red: [u8; 2] = if cfg!(target_endian = "little") { [0x00, 0xf8] } else { [0xf8, 0x00] };
+    for pixel in context.native_context().buffer().chunks(2) {
+        assert_eq!(pixel, &red[..]);
+    }
+
+    let config = OSMesaConfig {
+        format: 0,
+        .. Default::default()
+    };
+    assert!(NativeGLContext::new_with_config(None,
+                                             &gl::GlType::Gl,
+                                             GLVersion::Major(2),
+                                             &config).is_err());
+}
+
+#[cfg(all(target_os = "linux", feature = "test_egl_in_linux"))]
+fn test_create_on_display(api_version: GLVersion) {
+    use egl;
+    use egl::types::{EGLNativeDisplayType};
+
+    let display = unsafe { egl::GetDisplay(egl::DEFAULT_DISPLAY as EGLNativeDisplayType) };
+    let native = NativeGLContext::create_headless_on_display(display,
+                                                             &gl::GlType::default(),
+                                                             api_version,
+                                                             &GLContextAttributes::any()).unwrap();
+    let context = GLContext::new_from_native(native,
+                                             Size2D::new(256, 256),
+                                             GLContextAttributes::default(),
+                                             ColorAttachmentType::Texture,
+                                             gl::GlType::default(),
+                                             api_version).unwrap();
+    assert_eq!(context.handle().1, display);
+    test_gl_context(&context);
+}
+
+#[test]
+#[cfg(all(target_os = "linux", feature = "test_egl_in_linux"))]
+fn test_create_on_display_gl2() {
+    test_create_on_display(GLVersion::Major(2));
+}
+
+#[test]
+#[cfg(all(target_os = "linux", feature = "test_egl_in_linux"))]
+fn test_create_on_display_gl3() {
+    test_create_on_display(GLVersion::Major(3));
+}
+
+#[cfg(all(target_os = "linux", feature = "test_egl_in_linux"))]
+fn test_surfaceless(api_version: GLVersion) {
+    use egl;
+
+    let native = NativeGLContext::create_surfaceless(&gl::GlType::default(),
+                                                     api_version,
+                                                     &GLContextAttributes::any()).unwrap();
+    let context = GLContext::new_from_native(native,
+                                             Size2D::new(256, 256),
+                                             GLContextAttributes::default(),
+                                             ColorAttachmentType::Texture,
+                                             gl::GlType::default(),
+                                             api_version).unwrap();
+    assert_eq!(unsafe { egl::GetCurrentSurface(egl::DRAW as i32) }, egl::NO_SURFACE);
+    test_gl_context(&context);
+
+    // Contexts sharing with it don't get a pbuffer either.
+    let shared = GLContext::<NativeGLContext>::new(Size2D::new(256, 256),
+                                                   GLContextAttributes::default(),
+                                                   ColorAttachmentType::Texture,
+                                                   gl::GlType::default(),
+                                                   api_version,
+                                                   Some(&context.handle())).unwrap();
+    assert_eq!(unsafe { egl::GetCurrentSurface(egl::DRAW as i32) }, egl::NO_SURFACE);
+    test_gl_context(&shared);
+}
+
+#[test]
+#[cfg(all(target_os = "linux", feature = "test_egl_in_linux"))]
+fn test_surfaceless_gl2() {
+    test_surfaceless(GLVersion::Major(2));
+}
+
+#[test]
+#[cfg(all(target_os = "linux", feature = "test_egl_in_linux"))]
+fn test_surfaceless_gl3() {
+    test_surfaceless(GLVersion::Major(3));
+}
+
+#[test]
+fn test_robust_access() {
+    let attributes = GLContextAttributes {
+        robust_access: true,
+        .. Default::default()
+    };
+
+    let size = Size2D::new(256, 256);
+    let context = new_context(size, attributes, ColorAttachmentType::Texture, GLVersion::Major(3));
+    assert!(context.borrow_attributes().robust_access);
+    test_gl_context(&context);
+    assert_eq!(context.check_context_lost(), Ok(ContextLossStatus::NotLost));
+}
+
+#[test]
+fn test_resize_in_place() {
+    let size = Size2D::new(256, 256);
+    let mut context = new_context(size,
+                                  GLContextAttributes::default(),
+                                  ColorAttachmentType::Renderbuffer,
+                                  GLVersion::Major(3));
+    test_gl_context(&context);
+    let framebuffer = context.borrow_draw_buffer().unwrap().get_framebuffer();
+
+    let size = Size2D::new(64, 32);
+    context.resize(size).unwrap();
+    assert_eq!(context.borrow_draw_buffer().unwrap().get_framebuffer(), framebuffer);
+    assert_eq!(context.draw_buffer_size(), Some(size));
+    assert_eq!(context.borrow_draw_buffer().unwrap().color_attachment_type(),
+               ColorAttachmentType::Renderbuffer);
+
+    let vec = context.gl().read_pixels(0, 0, size.width, size.height, gl::RGBA, gl::UNSIGNED_BYTE);
+    test_pixels_eq(&vec, &[0, 0, 0, 0]);
+    test_gl_context(&context);
+}
+
+#[test]
+fn test_failed_resize() {
+    let size = Size2D::new(64, 32);
+    let attributes = GLContextAttributes {
+        antialias: true,
+        .. Default::default()
+    };
+    let mut context = new_context(size, attributes, ColorAttachmentType::Texture, GLVersion::Major(3));
+    context.gl().clear_color(1.0, 0.0, 0.0, 1.0);
+    context.gl().clear(gl::COLOR_BUFFER_BIT);
+    let texture = context.draw_buffer_texture().unwrap().0;
+
+    // Too large for GL, and then too large for the driver to allocate once
+    // multisampled, which fails halfway through.
+    let max_size = context.borrow_limits().max_renderbuffer_size as i32;
+    assert!(context.resize(Size2D::new(max_size + 1, 16)).is_err());
+    if context.needs_resolve() {
+        assert_eq!(context.resize(Size2D::new(max_size, max_size)), Err(OUT_OF_MEMORY));
+    }
+
+    // The old storage is still there, contents included...
+    assert_eq!(context.draw_buffer_size(), Some(size));
+    assert_eq!(context.draw_buffer_texture().unwrap().0, texture);
+    let mut pixels = vec![0; 64 * 32 * 4];
+    context.read_draw_buffer_into(&mut pixels).unwrap();
+    test_pixels(&pixels);
+
+    // ... and still works.
+    context.gl().clear_color(0.0, 1.0, 0.0, 1.0);
+    context.gl().clear(gl::COLOR_BUFFER_BIT);
+    context.read_draw_buffer_into(&mut pixels).unwrap();
+    test_pixels_eq(&pixels, &[0, 255, 0, 255]);
+    assert_eq!(context.gl().get_error(), gl::NO_ERROR);
+}
+
+#[test]
+fn test_out_of_memory() {
+    let context = GLContext::<NativeGLContext>::new(Size2D::new(256, 256),
+                                                    GLContextAttributes::default(),
+                                                    ColorAttachmentType::Renderbuffer,
+                                                    gl::GlType::default(),
+                                                    GLVersion::Major(3),
+                                                    None).unwrap();
+    if context.borrow_capabilities().max_samples == 0 {
+        return;
+    }
+
+    // The largest multisampled draw buffer we can ask for takes gigabytes,
+    // which the driver shouldn't be able to allocate.
+    let max_size = context.borrow_limits().max_renderbuffer_size as i32;
+    drop(context);
+    let attributes = GLContextAttributes {
+        antialias: true,
+        .. Default::default()
+    };
+    let result = GLContext::<NativeGLContext>::new(Size2D::new(max_size, max_size),
+                                                   attributes,
+                                                   ColorAttachmentType::Renderbuffer,
+                                                   gl::GlType::default(),
+                                                   GLVersion::Major(3),
+                                                   None);
+    match result {
+        Err(OUT_OF_MEMORY) => {},
+        Err(err) => panic!("Expected OUT_OF_MEMORY, got {:?}", err),
+        Ok(_) => panic!("Allocated a draw buffer of {0}x{0} pixels", max_size),
+    }
+}
+
+#[test]
+fn test_recoverable_context() {
+    let recreated = Rc::new(Cell::new(false));
+    let recreated_in_callback = recreated.clone();
+
+    let size = Size2D::new(256, 256);
+    let mut context =
+        RecoverableContext::<NativeGLContext>::new(size,
+                                                   GLContextAttributes::default(),
+                                                   ColorAttachmentType::Texture,
+                                                   gl::GlType::default(),
+                                                   GLVersion::Major(3),
+                                                   move |_| recreated_in_callback.set(true)).unwrap();
+    assert!(context.context().borrow_attributes().robust_access);
+    test_gl_context(context.context());
+
+    assert_eq!(context.recover_if_lost(), Ok(ContextLossStatus::NotLost));
+    assert!(!recreated.get());
+}
+
+#[test]
+fn test_debug_callback() {
+    let attributes = GLContextAttributes {
+        debug: true,
+        .. Default::default()
+    };
+
+    let size = Size2D::new(256, 256);
+    let mut context = new_context(size,
+                                  attributes,
+                                  ColorAttachmentType::Texture,
+                                  GLVersion::Major(3));
+
+    let messages = Rc::new(RefCell::new(vec![]));
+    let messages_in_callback = messages.clone();
+    context.set_debug_callback(move |message| {
+        messages_in_callback.borrow_mut().push(message);
+    }).unwrap();
+
+    // Not a texture target.
+    context.gl().bind_texture(gl::RGBA, 0);
+    assert_eq!(context.gl().get_error(), gl::INVALID_ENUM);
+    assert!(messages.borrow().iter().any(|message| message.ty == gl::DEBUG_TYPE_ERROR));
+
+    test_gl_context(&context);
+}
+
+#[test]
+fn test_no_error() {
+    let attributes = GLContextAttributes {
+        no_error: true,
+        .. Default::default()
+    };
+
+    let size = Size2D::new(256, 256);
+    let context = new_context(size, attributes, ColorAttachmentType::Texture, GLVersion::Major(3));
+    test_gl_context(&context);
+
+    let attributes = GLContextAttributes {
+        no_error: true,
+        robust_access: true,
+        .. Default::default()
+    };
+    assert!(GLContext::<NativeGLContext>::new(size,
+                                              attributes,
+                                              ColorAttachmentType::Texture,
+                                              gl::GlType::default(),
+                                              GLVersion::Major(3),
+                                              None).is_err());
+}
+
+#[test]
+fn test_context_priority() {
+    let attributes = GLContextAttributes {
+        priority: ContextPriority::High,
+        .. Default::default()
+    };
+
+    // It's just a hint, so this must work everywhere.
+    let size = Size2D::new(256, 256);
+    let context = new_context(size, attributes, ColorAttachmentType::Texture, GLVersion::Major(3));
+    assert_eq!(context.borrow_attributes().priority, ContextPriority::High);
+    test_gl_context(&context);
+}
+
+fn test_compatibility_profile(api_version: GLVersion) {
+    let attributes = GLContextAttributes {
+        profile: GLProfile::Compatibility,
+        .. Default::default()
+    };
+
+    let size = Size2D::new(256, 256);
+    let context = new_context(size, attributes, ColorAttachmentType::Texture, api_version);
+    assert_eq!(context.borrow_attributes().profile, GLProfile::Compatibility);
+    test_gl_context(&context);
+}
+
+#[test]
+fn test_compatibility_profile_gl2() {
+    test_compatibility_profile(GLVersion::Major(2));
+}
+
+#[test]
+#[cfg(not(target_os = "macos"))]
+fn test_compatibility_profile_gl3() {
+    test_compatibility_profile(GLVersion::Major(3));
+}
+
+#[cfg(all(target_os = "linux", feature = "test_egl_in_linux"))]
+fn test_from_raw_parts(api_version: GLVersion) {
+    use egl;
+    use egl::types::EGLint;
+
+    // Stands in for a context created by another library.
+    let owner = NativeGLContext::create_headless(&gl::GlType::default(), api_version).unwrap();
+    owner.make_current().unwrap();
+    let surface = unsafe { egl::GetCurrentSurface(egl::DRAW as EGLint) };
+    let handle = owner.handle();
+    owner.unbind().unwrap();
+
+    let native = unsafe { NativeGLContext::from_raw_parts(handle.1, handle.0, surface) };
+    let context = GLContext::new_from_native(native,
+                                             Size2D::new(256, 256),
+                                             GLContextAttributes::default(),
+                                             ColorAttachmentType::Texture,
+                                             gl::GlType::default(),
+                                             api_version).unwrap();
+    assert_eq!(context.native_context().surface(), surface);
+    test_gl_context(&context);
+    drop(context);
+
+    // The wrapper didn't destroy it.
+    owner.make_current().unwrap();
+    assert!(owner.is_current());
+}
+
+#[test]
+#[cfg(all(target_os = "linux", feature = "test_egl_in_linux"))]
+fn test_from_raw_parts_gl2() {
+    test_from_raw_parts(GLVersion::Major(2));
+}
+
+#[test]
+#[cfg(all(target_os = "linux", feature = "test_egl_in_linux"))]
+fn test_from_raw_parts_gl3() {
+    test_from_raw_parts(GLVersion::Major(3));
+}
+
+#[test]
+#[cfg(all(target_os = "linux", feature = "test_egl_in_linux"))]
+fn test_create_on_missing_drm_node() {
+    assert!(NativeGLContext::create_headless_on_drm_node("/dev/dri/no-such-node",
+                                                         &gl::GlType::default(),
+                                                         GLVersion::Major(2),
+                                                         &GLContextAttributes::any()).is_err());
+}